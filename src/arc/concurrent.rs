@@ -0,0 +1,188 @@
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+use Chainable;
+use tokenizer::{Tokenizer, WhitespaceTokenizer};
+use {States, Token};
+use super::ArcChain;
+
+/// The counts of the continuations of a subset of the chain's states.
+type Shard<T> = HashMap<Vec<Token<Arc<T>>>, HashMap<Token<Arc<T>>, usize>>;
+
+/// A subset of the distinct tokens fed into the chain, each with the position at which it was
+/// first fed relative to every other token.
+type TokenShard<T> = HashMap<Arc<T>, u64>;
+
+/// A Markov chain that can be fed from many threads at once. States are spread across several
+/// independently locked shards, so that concurrent feeds rarely wait on one another. The chain
+/// cannot generate; once training is done, it is converted into an `ArcChain` with
+/// `into_arc_chain`. Tokens are interned in a separate set of shards, so that each distinct token
+/// is only stored once however often it is fed.
+#[derive(Debug)]
+pub struct ConcurrentChain<T> where T: Chainable {
+    shards: Vec<Mutex<Shard<T>>>,
+    tokens: Vec<Mutex<TokenShard<T>>>,
+    fed_tokens: AtomicU64,
+    lengths: Mutex<BTreeMap<usize, usize>>,
+    hasher: RandomState,
+    order: usize,
+}
+
+impl<T> ConcurrentChain<T> where T: Chainable {
+    /// Constructs a new concurrent Markov chain, with four shards per available thread.
+    pub fn new() -> ConcurrentChain<T> {
+        let shards = 4 * thread::available_parallelism().map_or(1, |n| n.get());
+        ConcurrentChain {
+            shards: (0..shards).map(|_| Mutex::new(HashMap::new())).collect(),
+            tokens: (0..shards).map(|_| Mutex::new(HashMap::new())).collect(),
+            fed_tokens: AtomicU64::new(0),
+            lengths: Mutex::new(BTreeMap::new()),
+            hasher: RandomState::new(),
+            order: 1,
+        }
+    }
+
+    /// Choose a specific Markov chain order. The order is the number of previous tokens to use
+    /// as the index into the map. This should be chosen before anything is fed into the chain.
+    pub fn order(&mut self, order: usize) -> &mut ConcurrentChain<T> {
+        self.order = order;
+        self
+    }
+
+    /// Gets the order of the chain, i.e. the number of previous tokens used as the index into the
+    /// map.
+    pub fn get_order(&self) -> usize {
+        self.order
+    }
+
+    /// Feeds the chain a collection of tokens. Unlike `ArcChain::feed`, this only requires a
+    /// shared reference, so the chain can be fed from several threads at once.
+    pub fn feed<S: IntoIterator<Item = T>>(&self, tokens: S) -> &ConcurrentChain<T> {
+        let mut tokens = tokens.into_iter().peekable();
+        if tokens.peek().is_none() { return self }
+        let mut curs = vec!(None; self.order);
+        let mut len = 0;
+        for token in tokens {
+            let token = Some(self.intern(token));
+            self.add(curs.clone(), token.clone());
+            if self.order > 0 {
+                curs.remove(0);
+                curs.push(token);
+            }
+            len += 1;
+        }
+        self.add(curs, None);
+        let mut lengths = self.lengths.lock().unwrap();
+        let count = lengths.entry(len).or_insert(0);
+        *count = count.saturating_add(1);
+        self
+    }
+
+    /// Gets the shared pointer to the token, creating it if the token has not been fed before.
+    fn intern(&self, token: T) -> Arc<T> {
+        let shard = self.hasher.hash_one(&token) as usize % self.tokens.len();
+        let mut shard = self.tokens[shard].lock().unwrap();
+        if let Some((token, _)) = shard.get_key_value(&token) { return token.clone() }
+        let token = Arc::new(token);
+        shard.insert(token.clone(), self.fed_tokens.fetch_add(1, Ordering::Relaxed));
+        token
+    }
+
+    /// Records a single transition in the shard responsible for the state.
+    fn add(&self, state: Vec<Token<Arc<T>>>, token: Token<Arc<T>>) {
+        let shard = self.hasher.hash_one(&state) as usize % self.shards.len();
+        self.shards[shard].lock().unwrap().entry(state).or_default().add(token);
+    }
+
+    /// Converts the chain into an `ArcChain` that can be used for generation.
+    pub fn into_arc_chain(self) -> ArcChain<T> {
+        let mut chain = ArcChain::new();
+        chain.order(self.order);
+        let mut tokens: Vec<_> = self.tokens.into_iter()
+                                     .flat_map(|shard| shard.into_inner().unwrap())
+                                     .collect();
+        tokens.sort_by_key(|&(_, position)| position);
+        for (token, _) in tokens {
+            chain.learn(&Some(token));
+        }
+        for shard in self.shards {
+            for (state, states) in shard.into_inner().unwrap() {
+                let counts = chain.map.entry(state).or_default();
+                for (token, count) in states {
+                    let total = counts.entry(token).or_insert(0);
+                    *total = total.saturating_add(count);
+                }
+            }
+        }
+        chain.lengths = self.lengths.into_inner().unwrap();
+        chain
+    }
+}
+
+impl<T> Default for ConcurrentChain<T> where T: Chainable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConcurrentChain<String> {
+    /// Feeds a string of text into the chain, splitting it on whitespace.
+    pub fn feed_str(&self, string: &str) -> &ConcurrentChain<String> {
+        self.feed_str_with(string, &WhitespaceTokenizer)
+    }
+
+    /// Feeds a string of text into the chain, splitting it into tokens with the given tokenizer.
+    pub fn feed_str_with<K>(&self, string: &str, tokenizer: &K) -> &ConcurrentChain<String>
+        where K: Tokenizer {
+        self.feed(tokenizer.tokenize(string))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+    use super::ConcurrentChain;
+    use ArcChain;
+
+    #[test]
+    fn feed_from_threads() {
+        let mut chain = ConcurrentChain::new();
+        chain.order(2);
+        thread::scope(|scope| {
+            for i in 0..4u8 {
+                let chain = &chain;
+                scope.spawn(move || for j in 0..25 { chain.feed(vec![i, j % 3, j % 5]); });
+            }
+        });
+        let mut expected = ArcChain::new();
+        expected.order(2);
+        for i in 0..4u8 {
+            for j in 0..25 { expected.feed(vec![i, j % 3, j % 5]); }
+        }
+        assert_eq!(chain.into_arc_chain(), expected);
+    }
+
+    #[test]
+    fn feed_str() {
+        let chain = ConcurrentChain::new();
+        chain.feed_str("I like cats").feed_str("I like cats");
+        let chain = chain.into_arc_chain();
+        assert_eq!(chain.generate_str(), "I like cats");
+        let words: Vec<_> = chain.vocabulary().map(|(word, _)| &word[..]).collect();
+        assert_eq!(words, ["I", "like", "cats"]);
+    }
+
+    #[test]
+    fn intern_tokens() {
+        let chain = ConcurrentChain::new();
+        chain.feed(vec![1u8, 1, 1]);
+        let shard = chain.tokens.iter().find(|shard| !shard.lock().unwrap().is_empty()).unwrap();
+        let token = shard.lock().unwrap().keys().next().unwrap().clone();
+        assert_eq!(Arc::strong_count(&token), 5);
+    }
+}
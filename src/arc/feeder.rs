@@ -0,0 +1,85 @@
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use Chainable;
+use tokenizer::{Tokenizer, WhitespaceTokenizer};
+use super::ArcChain;
+
+/// A request sent to the feeding thread.
+enum Message<T> where T: Chainable {
+    /// Feed the tokens into the chain.
+    Feed(Vec<T>),
+    /// Send a copy of the chain back.
+    Snapshot(Sender<ArcChain<T>>),
+}
+
+/// A handle to an `ArcChain` owned by a background thread. Sequences sent with `feed` are queued
+/// and fed into the chain in order, without the caller waiting on the chain. A copy of the chain
+/// for generation can be taken at any time with `snapshot`.
+#[derive(Debug)]
+pub struct ChainFeeder<T> where T: Chainable {
+    sender: Sender<Message<T>>,
+    handle: JoinHandle<ArcChain<T>>,
+}
+
+impl<T> ChainFeeder<T> where T: Chainable + Send + Sync + 'static {
+    /// Moves the chain onto a new background thread.
+    pub fn new(mut chain: ArcChain<T>) -> ChainFeeder<T> {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            for message in receiver {
+                match message {
+                    Message::Feed(tokens) => { chain.feed(tokens); },
+                    Message::Snapshot(reply) => { let _ = reply.send(chain.clone()); },
+                }
+            }
+            chain
+        });
+        ChainFeeder { sender, handle }
+    }
+
+    /// Queues a collection of tokens to be fed into the chain, returning immediately.
+    pub fn feed<S: IntoIterator<Item = T>>(&self, tokens: S) -> &ChainFeeder<T> {
+        self.sender.send(Message::Feed(tokens.into_iter().collect())).unwrap();
+        self
+    }
+
+    /// Gets a copy of the chain, once every collection queued before this call has been fed.
+    pub fn snapshot(&self) -> ArcChain<T> {
+        let (reply, receiver) = mpsc::channel();
+        self.sender.send(Message::Snapshot(reply)).unwrap();
+        receiver.recv().unwrap()
+    }
+
+    /// Stops the background thread once every queued collection has been fed, and returns the
+    /// chain.
+    pub fn finish(self) -> ArcChain<T> {
+        drop(self.sender);
+        self.handle.join().unwrap()
+    }
+}
+
+impl ChainFeeder<String> {
+    /// Queues a string of text to be fed into the chain, splitting it on whitespace.
+    pub fn feed_str(&self, string: &str) -> &ChainFeeder<String> {
+        self.feed(WhitespaceTokenizer.tokenize(string))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChainFeeder;
+    use ArcChain;
+
+    #[test]
+    fn feed_and_snapshot() {
+        let feeder = ChainFeeder::new(ArcChain::new());
+        assert!(feeder.snapshot().is_empty());
+        feeder.feed_str("I like cats");
+        assert_eq!(feeder.snapshot().generate_str(), "I like cats");
+        feeder.feed(vec!["I".to_owned(), "like".to_owned(), "dogs".to_owned()]);
+        let mut expected = ArcChain::new();
+        expected.feed_str("I like cats").feed_str("I like dogs");
+        assert_eq!(feeder.finish(), expected);
+    }
+}
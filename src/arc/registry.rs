@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use Chainable;
+use super::{ArcChain, SharedChain};
+
+/// A model held by the registry.
+struct Model<T> where T: Chainable {
+    /// The chain, if it is currently loaded.
+    chain: Option<SharedChain<T>>,
+    /// Loads the chain again after it has been evicted. Models without a loader are never evicted.
+    loader: Option<Box<dyn Fn() -> ArcChain<T> + Send + Sync>>,
+    /// The value of the registry's clock when the model was last looked up.
+    last_used: u64,
+    /// The approximate number of bytes used by the chain, measured when it was loaded.
+    memory: usize,
+}
+
+/// A collection of named chains for services hosting many models at once. The registry keeps the
+/// approximate memory used by loaded chains within a budget by evicting the least recently used
+/// chains that can be loaded again on demand. Only available with the `registry` feature enabled.
+///
+/// Chains are handed out as `SharedChain` handles. An evicted chain is only freed once every
+/// handle to it has been dropped, so handles should not be held for longer than necessary. The
+/// size of each chain is measured once, when it is loaded, so chains fed through their handles
+/// afterwards are not accounted for.
+pub struct ModelRegistry<T> where T: Chainable {
+    models: HashMap<String, Model<T>>,
+    budget: usize,
+    clock: u64,
+    usage: usize,
+}
+
+impl<T> ModelRegistry<T> where T: Chainable {
+    /// Constructs an empty registry that keeps loaded chains within the given number of bytes.
+    pub fn new(budget: usize) -> ModelRegistry<T> {
+        ModelRegistry { models: HashMap::new(), budget, clock: 0, usage: 0 }
+    }
+
+    /// Adds a chain under the given name, replacing any model of the same name. As it cannot be
+    /// loaded again, the chain is never evicted.
+    pub fn register(&mut self, name: &str, chain: ArcChain<T>) -> &mut ModelRegistry<T> {
+        let memory = chain.stats().memory;
+        let chain = Some(SharedChain::from(chain));
+        let model = Model { chain, loader: None, last_used: 0, memory };
+        self.insert(name, model);
+        self
+    }
+
+    /// Adds a model under the given name, replacing any model of the same name. The chain is
+    /// loaded with the given function the first time it is looked up, and again whenever it is
+    /// looked up after being evicted.
+    pub fn register_with_loader<F>(&mut self, name: &str, loader: F) -> &mut ModelRegistry<T>
+        where F: Fn() -> ArcChain<T> + Send + Sync + 'static {
+        let model = Model { chain: None, loader: Some(Box::new(loader)), last_used: 0, memory: 0 };
+        self.insert(name, model);
+        self
+    }
+
+    /// Adds a model, replacing any model of the same name and accounting for the memory used by
+    /// both.
+    fn insert(&mut self, name: &str, model: Model<T>) {
+        self.usage += model.memory;
+        if let Some(replaced) = self.models.insert(name.to_owned(), model) {
+            self.usage -= replaced.memory;
+        }
+    }
+
+    /// Removes the model with the given name, returning whether it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.models.remove(name) {
+            Some(model) => {
+                self.usage -= model.memory;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Looks up the chain with the given name, loading it if necessary. If loading the chain puts
+    /// the registry over its budget, other chains are evicted, least recently used first, until it
+    /// is back within budget or nothing else can be evicted.
+    pub fn get(&mut self, name: &str) -> Option<SharedChain<T>> {
+        self.clock += 1;
+        let chain = {
+            let model = self.models.get_mut(name)?;
+            model.last_used = self.clock;
+            if model.chain.is_none() {
+                let chain = model.loader.as_ref()?();
+                model.memory = chain.stats().memory;
+                model.chain = Some(SharedChain::from(chain));
+                self.usage += model.memory;
+            }
+            model.chain.clone()?
+        };
+        self.evict(name);
+        Some(chain)
+    }
+
+    /// Determines whether the model with the given name is currently loaded.
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.models.get(name).is_some_and(|model| model.chain.is_some())
+    }
+
+    /// Gets the approximate number of bytes used by every loaded chain, excluding the tokens
+    /// themselves, as measured when each chain was loaded.
+    pub fn memory_usage(&self) -> usize {
+        self.usage
+    }
+
+    /// Evicts the least recently used chains other than the named one until the registry is
+    /// within budget.
+    fn evict(&mut self, keep: &str) {
+        while self.usage > self.budget {
+            let victim = self.models.iter_mut()
+                             .filter(|(name, model)| {
+                                 *name != keep && model.chain.is_some() && model.loader.is_some()
+                             })
+                             .min_by_key(|(_, model)| model.last_used);
+            match victim {
+                Some((_, model)) => {
+                    model.chain = None;
+                    self.usage -= model.memory;
+                    model.memory = 0;
+                },
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ModelRegistry;
+    use ArcChain;
+
+    fn chain(string: &str) -> ArcChain<String> {
+        let mut chain = ArcChain::new();
+        chain.feed_str(string);
+        chain
+    }
+
+    #[test]
+    fn register_and_get() {
+        let mut registry = ModelRegistry::new(0);
+        registry.register("cats", chain("I like cats"));
+        assert_eq!(registry.get("cats").unwrap().generate_str(), "I like cats");
+        assert!(registry.get("dogs").is_none());
+        assert!(registry.remove("cats"));
+        assert!(registry.get("cats").is_none());
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut registry = ModelRegistry::new(0);
+        registry.register_with_loader("cats", || chain("I like cats"))
+                .register_with_loader("dogs", || chain("I like dogs"));
+        assert!(!registry.is_loaded("cats"));
+        let size = {
+            registry.get("cats");
+            registry.memory_usage()
+        };
+        registry.budget = 2 * size;
+        assert_eq!(registry.get("dogs").unwrap().generate_str(), "I like dogs");
+        assert!(registry.is_loaded("cats") && registry.is_loaded("dogs"));
+        registry.budget = size;
+        registry.get("dogs");
+        assert!(!registry.is_loaded("cats") && registry.is_loaded("dogs"));
+        assert_eq!(registry.get("cats").unwrap().generate_str(), "I like cats");
+        assert!(registry.is_loaded("cats") && !registry.is_loaded("dogs"));
+        assert_eq!(registry.memory_usage(), size);
+        registry.register("birds", chain("I like birds"));
+        assert_eq!(registry.memory_usage(), 2 * size);
+        assert!(registry.remove("cats"));
+        assert_eq!(registry.memory_usage(), size);
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use Chainable;
+use super::ArcChain;
+
+/// A handle to an `ArcChain` that can be fed and generated from by many threads at once. Cloning
+/// the handle is cheap, and every clone refers to the same chain. Feeding takes the write lock and
+/// generation takes the read lock, so generation only waits on feeding and never on other
+/// generation.
+#[derive(Debug)]
+pub struct SharedChain<T> where T: Chainable {
+    chain: Arc<RwLock<ArcChain<T>>>,
+}
+
+impl<T> SharedChain<T> where T: Chainable {
+    /// Constructs a handle to a new, empty Markov chain.
+    pub fn new() -> SharedChain<T> {
+        SharedChain::from(ArcChain::new())
+    }
+
+    /// Feeds the chain a collection of tokens.
+    pub fn feed<S: IntoIterator<Item = T>>(&self, tokens: S) -> &SharedChain<T> {
+        self.write().feed(tokens);
+        self
+    }
+
+    /// Generates a collection of tokens from the chain.
+    pub fn generate(&self) -> Vec<Arc<T>> {
+        self.read().generate()
+    }
+
+    /// Generates a collection of tokens from the chain, starting with the given token. This
+    /// returns an empty vector if the token is not found.
+    pub fn generate_from_token(&self, token: T) -> Vec<Arc<T>> {
+        self.read().generate_from_token(token)
+    }
+
+    /// Determines whether or not the chain is empty.
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Locks the chain for reading, for operations not exposed directly on the handle. This
+    /// blocks while the chain is being fed.
+    pub fn read(&self) -> RwLockReadGuard<'_, ArcChain<T>> {
+        self.chain.read().unwrap()
+    }
+
+    /// Locks the chain for writing, for operations not exposed directly on the handle. This
+    /// blocks while the chain is being fed or generated from.
+    pub fn write(&self) -> RwLockWriteGuard<'_, ArcChain<T>> {
+        self.chain.write().unwrap()
+    }
+}
+
+impl<T> Clone for SharedChain<T> where T: Chainable {
+    fn clone(&self) -> SharedChain<T> {
+        SharedChain { chain: self.chain.clone() }
+    }
+}
+
+impl<T> Default for SharedChain<T> where T: Chainable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<ArcChain<T>> for SharedChain<T> where T: Chainable {
+    fn from(chain: ArcChain<T>) -> SharedChain<T> {
+        SharedChain { chain: Arc::new(RwLock::new(chain)) }
+    }
+}
+
+impl SharedChain<String> {
+    /// Feeds a string of text into the chain.
+    pub fn feed_str(&self, string: &str) -> &SharedChain<String> {
+        self.write().feed_str(string);
+        self
+    }
+
+    /// Generates a random string of text.
+    pub fn generate_str(&self) -> String {
+        self.read().generate_str()
+    }
+
+    /// Generates a random string of text starting with the desired token. This returns an empty
+    /// string if the token is not found.
+    pub fn generate_str_from_token(&self, string: &str) -> String {
+        self.read().generate_str_from_token(string)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use super::SharedChain;
+
+    #[test]
+    fn feed_and_generate_from_threads() {
+        let chain = SharedChain::new();
+        let handles: Vec<_> = (0..4).map(|_| {
+            let chain = chain.clone();
+            thread::spawn(move || {
+                chain.feed_str("I like cats");
+                chain.generate_str()
+            })
+        }).collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "I like cats");
+        }
+        assert_eq!(chain.read().generate_str_from_token("like"), "like cats");
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use rand::{Rng, thread_rng};
+
+use {Chainable, States, Token, Transitions, sum_counts};
+use tokenizer::{Detokenizer, Tokenizer, WhitespaceDetokenizer, WhitespaceTokenizer};
+
+/// A Markov chain that records the continuations of contexts of every length up to its order.
+/// During generation, it backs off from the full context to shorter ones when the full context
+/// was rarely observed. A `Chain` of high order tends to reproduce the fed collections verbatim,
+/// as most of its states have a single continuation; backing off lets the output mix them.
+#[derive(PartialEq, Debug)]
+pub struct BackoffChain<T> where T: Chainable {
+    map: Transitions<Rc<T>>,
+    order: usize,
+    min_count: usize,
+}
+
+impl<T> BackoffChain<T> where T: Chainable {
+    /// Constructs a new backoff chain.
+    pub fn new() -> BackoffChain<T> {
+        BackoffChain { map: HashMap::new(), order: 1, min_count: 1 }
+    }
+
+    /// Choose the maximum order of the chain, i.e. the length of the longest context used to
+    /// choose the next token. This should be chosen before anything is fed into the chain.
+    pub fn order(&mut self, order: usize) -> &mut BackoffChain<T> {
+        self.order = order;
+        self
+    }
+
+    /// Choose how many times a context must have been observed for generation to use it, rather
+    /// than backing off to the next shorter context. Raising this makes the output more varied
+    /// and less coherent. The default is one, i.e. backing off only from unseen contexts.
+    pub fn min_count(&mut self, min_count: usize) -> &mut BackoffChain<T> {
+        self.min_count = min_count;
+        self
+    }
+
+    /// Gets the maximum order of the chain.
+    pub fn get_order(&self) -> usize {
+        self.order
+    }
+
+    /// Determines whether or not the chain is empty.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Feeds the chain a collection of tokens, recording each transition under every context
+    /// length. An empty collection leaves the chain unchanged.
+    pub fn feed<S: IntoIterator<Item = T>>(&mut self, tokens: S) -> &mut BackoffChain<T> {
+        let mut tokens = tokens.into_iter().peekable();
+        if tokens.peek().is_none() { return self }
+        let mut history = vec!(None; self.order);
+        for token in tokens.map(|token| Some(Rc::new(token))).chain(Some(None)) {
+            for len in 0..=self.order {
+                let context = history[self.order - len..].to_vec();
+                self.map.entry(context).or_default().add(token.clone());
+            }
+            self.advance(&mut history, token);
+        }
+        self
+    }
+
+    /// Generates a collection of tokens from the chain.
+    pub fn generate(&self) -> Vec<Rc<T>> {
+        self.generate_with_rng(&mut thread_rng())
+    }
+
+    /// Generates a collection of tokens from the chain using the given random number generator.
+    pub fn generate_with_rng<R: Rng>(&self, rng: &mut R) -> Vec<Rc<T>> {
+        let mut ret = Vec::new();
+        let mut history = vec!(None; self.order);
+        loop {
+            let next = self.continuations(&history).and_then(|states| states.next(rng));
+            match next {
+                Some(next) => {
+                    self.advance(&mut history, Some(next.clone()));
+                    ret.push(next);
+                },
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// Gets the continuations of the longest context ending the history that was observed at
+    /// least `min_count` times, falling back to the empty context.
+    fn continuations(&self, history: &[Token<Rc<T>>]) -> Option<&HashMap<Token<Rc<T>>, usize>> {
+        let observed = |len: &usize| {
+            self.map.get(&history[self.order - len..])
+                .is_some_and(|states| sum_counts(states.values()) >= self.min_count as u64)
+        };
+        let len = (1..=self.order).rev().find(observed).unwrap_or(0);
+        self.map.get(&history[self.order - len..]).filter(|states| !states.is_empty())
+    }
+
+    /// Moves the history along by one token.
+    fn advance(&self, history: &mut Vec<Token<Rc<T>>>, token: Token<Rc<T>>) {
+        if self.order > 0 {
+            history.remove(0);
+            history.push(token);
+        }
+    }
+}
+
+impl<T> Default for BackoffChain<T> where T: Chainable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BackoffChain<String> {
+    /// Feeds a string of text into the chain, splitting it on whitespace.
+    pub fn feed_str(&mut self, string: &str) -> &mut BackoffChain<String> {
+        self.feed(WhitespaceTokenizer.tokenize(string))
+    }
+
+    /// Generates a random string of text.
+    pub fn generate_str(&self) -> String {
+        let tokens = self.generate();
+        let tokens: Vec<_> = tokens.iter().map(|s| &s[..]).collect();
+        WhitespaceDetokenizer.detokenize(&tokens)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BackoffChain;
+
+    #[test]
+    fn back_off_from_rare_contexts() {
+        let mut chain = BackoffChain::new();
+        chain.order(2).feed_str("a b c").feed_str("x b y");
+        let fed = ["a b c", "x b y"];
+        assert!((0..100).all(|_| fed.contains(&&chain.generate_str()[..])));
+        chain.min_count(2);
+        assert!((0..100).any(|_| !fed.contains(&&chain.generate_str()[..])));
+    }
+
+    #[test]
+    fn empty() {
+        let mut chain = BackoffChain::<u8>::new();
+        assert!(chain.is_empty() && chain.generate().is_empty());
+        chain.feed(vec![]);
+        assert!(chain.is_empty());
+        chain.feed(vec![1]);
+        assert!(!chain.is_empty());
+    }
+}
@@ -22,6 +22,7 @@ fn main() {
 /// to be generated. This number must be a positive, non-zero integer. `-o #` is also supported to
 /// specify the order of Markov chain to be used. Note `-o` must be specified before any file
 /// names and must also be a positive, non-zero integer.
+/// Files that cannot be read, e.g. because they are not valid UTF-8, are skipped with a warning.
 ///
 /// Some valid usages of this function:
 /// `markov_gen(vec!["test".to_owned()])`
@@ -43,7 +44,7 @@ fn markov_gen(args: Vec<String>) -> Vec<String>{
     opts.optflag("h", "help", "print this help menu");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(e) => panic!(e.to_string()),
+        Err(e) => panic!("{}", e),
     };
     if matches.opt_present("h") {
         let brief = format!("Usage: {} FILE1 [FILE2 FILE3 ...] [options]", args[0]);
@@ -65,7 +66,9 @@ fn markov_gen(args: Vec<String>) -> Vec<String>{
             }
         }
         for path in matches.free.iter() {
-            chain.feed_file(Path::new(&path));
+            if let Err(e) = chain.try_feed_file(Path::new(&path)) {
+                eprintln!("Skipped {}: {}", path, e);
+            }
         }
         if chain.is_empty() { panic!("No files were fed into the chain.") }
         chain.str_iter_for(count).collect()
@@ -0,0 +1,260 @@
+#[cfg(feature = "repl")] extern crate markov;
+
+#[cfg(all(feature = "repl", not(test)))] use std::env::args;
+#[cfg(feature = "repl")] use std::fs::File;
+#[cfg(all(feature = "repl", not(test)))] use std::io::{stdin, stdout};
+#[cfg(feature = "repl")] use std::io::{self, BufRead, BufReader, BufWriter, Write};
+#[cfg(feature = "repl")] use markov::Chain;
+
+#[cfg(all(feature = "repl", not(test)))]
+fn main() {
+    let mut repl = Repl::new();
+    for path in args().skip(1) {
+        for line in repl.handle(&format!(":load {}", path)).unwrap_or_default() {
+            println!("{}", line);
+        }
+    }
+    let stdin = stdin();
+    let mut out = stdout();
+    print!("> ");
+    out.flush().unwrap();
+    for line in stdin.lock().lines() {
+        let output = match line {
+            Ok(line) => repl.handle(&line),
+            Err(e) => Some(vec![format!("Failed to read input: {}", e)]),
+        };
+        match output {
+            Some(output) => for line in output { println!("{}", line) },
+            None => break,
+        }
+        print!("> ");
+        out.flush().unwrap();
+    }
+}
+
+#[cfg(all(not(feature = "repl"), not(test)))]
+fn main() {
+    println!("markov-repl must be compiled with the repl feature enabled.")
+}
+
+/// The help text printed by `:help`.
+#[cfg(feature = "repl")]
+const HELP: &str = "\
+:gen [N]        generate N lines (an empty line generates one)
+:gen WORD       generate a line starting with WORD
+:seed [WORD]    start generated lines with WORD, or clear the seed
+:order N        rebuild the chain with order N
+:feed TEXT      feed a line of text into the chain (as does any line not starting with :)
+:load FILE      feed a file into the chain, one sentence per line
+:save FILE      save the chain, for use with markov generate
+:stats          print the size of the chain
+:help           print this message
+:quit           exit the repl";
+
+/// An interactive session over a chain of strings. Every line fed into the session is kept so that
+/// the chain can be rebuilt when its order changes.
+#[cfg(feature = "repl")]
+struct Repl {
+    chain: Chain<String>,
+    lines: Vec<String>,
+    order: usize,
+    seed: Option<String>,
+}
+
+#[cfg(feature = "repl")]
+impl Repl {
+    /// Constructs a new session over an empty first-order chain.
+    fn new() -> Repl {
+        Repl { chain: Chain::new(), lines: Vec::new(), order: 1, seed: None }
+    }
+
+    /// Handles a single line of input, returning the lines to print, or `None` if the session
+    /// should end.
+    fn handle(&mut self, input: &str) -> Option<Vec<String>> {
+        let input = input.trim();
+        let (command, arg) = match input.find(' ') {
+            Some(i) => (&input[..i], input[i + 1..].trim()),
+            None => (input, ""),
+        };
+        Some(match command {
+            "" => self.generate(1),
+            ":gen" => match arg {
+                "" => self.generate(1),
+                arg => match arg.parse() {
+                    Ok(n) if n > 0 => self.generate(n),
+                    Ok(_) => vec![format!("Expected positive integer argument to :gen, found {}.",
+                                          arg)],
+                    Err(_) => self.generate_from(arg),
+                },
+            },
+            ":seed" if arg.is_empty() => {
+                self.seed = None;
+                vec!["Cleared the seed.".to_owned()]
+            },
+            ":seed" => {
+                self.seed = Some(arg.to_owned());
+                vec![format!("Seeded generation with {}.", arg)]
+            },
+            ":order" => match arg.parse() {
+                Ok(n) if n > 0 => {
+                    self.order = n;
+                    self.rebuild();
+                    vec![format!("Rebuilt the chain with order {}.", n)]
+                },
+                _ => vec![format!("Expected positive integer argument to :order, found {}.", arg)],
+            },
+            ":feed" => {
+                self.feed(arg.to_owned());
+                Vec::new()
+            },
+            ":load" => match File::open(arg) {
+                Ok(file) => {
+                    let before = self.lines.len();
+                    for line in BufReader::new(file).lines() {
+                        match line {
+                            Ok(line) => self.feed(line),
+                            Err(e) => return Some(vec![format!("Failed to read {}: {}", arg, e)]),
+                        }
+                    }
+                    vec![format!("Loaded {} lines from {}.", self.lines.len() - before, arg)]
+                },
+                Err(e) => vec![format!("Failed to open {}: {}", arg, e)],
+            },
+            ":save" => match self.save(arg) {
+                Ok(()) => vec![format!("Saved the chain to {}.", arg)],
+                Err(e) => vec![format!("Failed to save {}: {}", arg, e)],
+            },
+            ":stats" => {
+                let stats = self.chain.stats();
+                vec![format!("{} states, {} transitions, {} distinct tokens, {} tokens fed.",
+                             stats.states, stats.transitions, stats.vocabulary, stats.tokens)]
+            },
+            ":help" => HELP.lines().map(|s| s.to_owned()).collect(),
+            ":quit" | ":q" => return None,
+            _ if command.starts_with(':') => vec![format!("Unknown command {}, try :help.",
+                                                          command)],
+            _ => {
+                self.feed(input.to_owned());
+                Vec::new()
+            },
+        })
+    }
+
+    /// Feeds a line into the chain, remembering it for later rebuilds.
+    fn feed(&mut self, line: String) {
+        self.chain.feed_str(&line);
+        self.lines.push(line);
+    }
+
+    /// Rebuilds the chain from every line fed so far using the current order.
+    fn rebuild(&mut self) {
+        self.chain = Chain::new();
+        self.chain.order(self.order);
+        for line in &self.lines {
+            self.chain.feed_str(line);
+        }
+    }
+
+    /// Saves the chain to the given file.
+    fn save(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.chain.save(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Generates a line starting with the given word, ignoring the current seed.
+    fn generate_from(&self, word: &str) -> Vec<String> {
+        if !self.chain.contains_token(&word.to_owned()) {
+            return vec![format!("The word {} has not been fed into the chain.", word)]
+        }
+        vec![self.chain.generate_str_from_token(word)]
+    }
+
+    /// Generates the specified number of lines, honoring the current seed.
+    fn generate(&self, count: usize) -> Vec<String> {
+        if self.chain.is_empty() {
+            return vec!["The chain is empty, try :load or :feed.".to_owned()]
+        }
+        (0..count).map(|_| match self.seed {
+            Some(ref seed) => self.chain.generate_str_from_token(seed),
+            None => self.chain.generate_str(),
+        }).collect()
+    }
+}
+
+#[cfg(all(feature = "repl", test))]
+mod test {
+    use std::{env, fs, process};
+    use super::Repl;
+
+    #[test]
+    fn empty_chain() {
+        let repl = Repl::new();
+        assert_eq!(repl.generate(1), vec!["The chain is empty, try :load or :feed."]);
+    }
+
+    #[test]
+    fn feed_and_gen() {
+        let mut repl = Repl::new();
+        repl.handle(":feed I like cats");
+        assert_eq!(repl.handle(":gen 2").unwrap(), vec!["I like cats", "I like cats"]);
+        assert_eq!(repl.handle("").unwrap(), vec!["I like cats"]);
+    }
+
+    #[test]
+    fn load() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.handle(":load test").unwrap().len(), 1);
+        assert!(!repl.chain.is_empty());
+    }
+
+    #[test]
+    fn load_invalid_utf8() {
+        let path = env::temp_dir().join(format!("markov-repl-binary-{}.txt", process::id()));
+        fs::write(&path, b"I like cats\n\xff\n").unwrap();
+        let path = path.to_str().unwrap();
+        let mut repl = Repl::new();
+        let output = repl.handle(&format!(":load {}", path)).unwrap();
+        assert!(output[0].starts_with(&format!("Failed to read {}", path)));
+        assert_eq!(repl.handle(":gen").unwrap(), vec!["I like cats"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn seed() {
+        let mut repl = Repl::new();
+        repl.handle(":feed I like cats");
+        repl.handle(":seed like");
+        assert_eq!(repl.handle(":gen").unwrap(), vec!["like cats"]);
+        repl.handle(":seed");
+        assert_eq!(repl.handle(":gen").unwrap(), vec!["I like cats"]);
+    }
+
+    #[test]
+    fn order() {
+        let mut repl = Repl::new();
+        repl.handle(":feed I like cats");
+        repl.handle(":order 2");
+        assert_eq!(repl.handle(":gen").unwrap(), vec!["I like cats"]);
+        assert_eq!(repl.handle(":order 0").unwrap(),
+                   vec!["Expected positive integer argument to :order, found 0."]);
+    }
+
+    #[test]
+    fn feed_plain_lines() {
+        let mut repl = Repl::new();
+        assert!(repl.handle("I like cats").unwrap().is_empty());
+        assert_eq!(repl.handle(":gen like").unwrap(), vec!["like cats"]);
+        assert_eq!(repl.handle(":gen dogs").unwrap(),
+                   vec!["The word dogs has not been fed into the chain."]);
+        assert_eq!(repl.handle(":stats").unwrap(),
+                   vec!["4 states, 4 transitions, 3 distinct tokens, 3 tokens fed."]);
+        assert_eq!(repl.handle(":frobnicate").unwrap(),
+                   vec!["Unknown command :frobnicate, try :help."]);
+    }
+
+    #[test]
+    fn quit() {
+        assert_eq!(Repl::new().handle(":quit"), None);
+    }
+}
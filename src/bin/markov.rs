@@ -0,0 +1,197 @@
+#[cfg(feature = "cli")] extern crate getopts;
+#[cfg(feature = "cli")] extern crate markov;
+
+#[cfg(all(feature = "cli", not(test)))] use std::env::args;
+#[cfg(all(feature = "cli", not(test)))] use std::process::exit;
+#[cfg(feature = "cli")] use std::fs::File;
+#[cfg(feature = "cli")] use std::io::{BufReader, BufWriter, Write};
+#[cfg(feature = "cli")] use getopts::{Matches, Options};
+#[cfg(feature = "cli")] use markov::Chain;
+#[cfg(feature = "cli")] use markov::convert::{Format, convert};
+
+#[cfg(all(feature = "cli", not(test)))]
+fn main() {
+    match run(&args().collect::<Vec<_>>()) {
+        Ok(output) => for line in output { println!("{}", line) },
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        },
+    }
+}
+
+#[cfg(all(not(feature = "cli"), not(test)))]
+fn main() {
+    println!("markov must be compiled with the cli feature enabled.")
+}
+
+/// The summary of the subcommands printed above the options by `--help`.
+#[cfg(feature = "cli")]
+const USAGE: &str = "\
+Usage: markov train CORPUS... -o MODEL [-r ORDER]
+       markov generate MODEL [-n COUNT] [-s WORD]
+       markov stats MODEL
+       markov convert INPUT OUTPUT";
+
+/// Runs the subcommand given by the arguments, returning the lines to print or a message
+/// describing why it failed. `train` feeds each corpus into a chain, one sentence per line, and
+/// saves it to the model file. `generate` prints phrases generated from a saved model, `stats`
+/// prints a summary of its size, and `convert` rewrites a model between the text and JSON
+/// formats, choosing JSON for files ending in `.json`.
+#[cfg(feature = "cli")]
+fn run(args: &[String]) -> Result<Vec<String>, String> {
+    let mut opts = Options::new();
+    opts.optopt("o", "output", "set the file the trained model is saved to", "MODEL");
+    opts.optopt("r", "order", "set the order of the trained Markov chain", "ORDER");
+    opts.optopt("n", "count", "set the number of phrases to generate", "COUNT");
+    opts.optopt("s", "seed", "start each generated phrase with the given word", "WORD");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[1..]).map_err(|e| e.to_string())?;
+    if matches.opt_present("h") {
+        return Ok(vec![opts.usage(USAGE)])
+    }
+    match matches.free.first().map(|command| &command[..]) {
+        Some("train") => train(&matches),
+        Some("generate") => generate(&matches),
+        Some("stats") => stats(&matches),
+        Some("convert") => convert_model(&matches),
+        Some(command) => Err(format!("Unknown command {}, try --help.", command)),
+        None => Err(opts.usage(USAGE)),
+    }
+}
+
+/// Feeds the corpora into a chain and saves it.
+#[cfg(feature = "cli")]
+fn train(matches: &Matches) -> Result<Vec<String>, String> {
+    let output = matches.opt_str("o").ok_or("Expected a model file to save to, given by -o.")?;
+    let mut chain = Chain::new();
+    chain.order(positive(matches, "order", 1)?);
+    if matches.free.len() < 2 { return Err("Expected at least one corpus to train on.".to_owned()) }
+    for path in &matches.free[1..] {
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        chain.try_feed_reader(BufReader::new(file))
+             .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    }
+    let file = File::create(&output).map_err(|e| format!("Failed to create {}: {}", output, e))?;
+    let mut writer = BufWriter::new(file);
+    chain.save(&mut writer).and_then(|_| writer.flush())
+         .map_err(|e| format!("Failed to save {}: {}", output, e))?;
+    Ok(vec![format!("Saved a chain of {} states to {}.", chain.len(), output)])
+}
+
+/// Generates phrases from a saved model.
+#[cfg(feature = "cli")]
+fn generate(matches: &Matches) -> Result<Vec<String>, String> {
+    let chain = load(matches)?;
+    let count = positive(matches, "count", 1)?;
+    if chain.is_empty() { return Err("The model is empty.".to_owned()) }
+    match matches.opt_str("s") {
+        Some(seed) => {
+            if !chain.contains_token(&seed) {
+                return Err(format!("The seed {} does not appear in the model.", seed))
+            }
+            Ok((0..count).map(|_| chain.generate_str_from_token(&seed)).collect())
+        },
+        None => Ok(chain.str_iter_for(count).collect()),
+    }
+}
+
+/// Summarizes a saved model.
+#[cfg(feature = "cli")]
+fn stats(matches: &Matches) -> Result<Vec<String>, String> {
+    let chain = load(matches)?;
+    let stats = chain.stats();
+    Ok(vec![
+        format!("order: {}", chain.get_order()),
+        format!("states: {}", stats.states),
+        format!("transitions: {}", stats.transitions),
+        format!("vocabulary: {}", stats.vocabulary),
+        format!("tokens: {}", stats.tokens),
+        format!("branching: {:.2}", stats.branching),
+        format!("memory: {} bytes", stats.memory),
+    ])
+}
+
+/// Converts a saved model to the format of the output file.
+#[cfg(feature = "cli")]
+fn convert_model(matches: &Matches) -> Result<Vec<String>, String> {
+    let (input, output) = match matches.free.get(1..3) {
+        Some(paths) => (&paths[0], &paths[1]),
+        None => return Err("Expected a model file to convert and a file to write.".to_owned()),
+    };
+    let file = File::open(input).map_err(|e| format!("Failed to open {}: {}", input, e))?;
+    let mut writer = BufWriter::new(
+        File::create(output).map_err(|e| format!("Failed to create {}: {}", output, e))?
+    );
+    let (from, to) = (Format::from_path(input), Format::from_path(output));
+    convert(BufReader::new(file), from, &mut writer, to).and_then(|_| writer.flush())
+        .map_err(|e| format!("Failed to convert {}: {}", input, e))?;
+    Ok(vec![format!("Converted {} to {}.", input, output)])
+}
+
+/// Loads the model named by the first operand after the subcommand.
+#[cfg(feature = "cli")]
+fn load(matches: &Matches) -> Result<Chain<String>, String> {
+    let path = matches.free.get(1).ok_or("Expected a model file to load.")?;
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    Chain::load(BufReader::new(file)).map_err(|e| format!("Failed to load {}: {}", path, e))
+}
+
+/// Parses the named option as a positive integer, falling back to the default if it is absent.
+#[cfg(feature = "cli")]
+fn positive(matches: &Matches, name: &str, default: usize) -> Result<usize, String> {
+    match matches.opt_str(name) {
+        Some(arg) => match arg.parse() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(format!("Expected positive integer argument to --{}, found {}.", name, arg)),
+        },
+        None => Ok(default),
+    }
+}
+
+#[cfg(all(feature = "cli", test))]
+mod test {
+    use std::{env, fs, process};
+    use super::run;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split(' ').map(|arg| arg.to_owned()).collect()
+    }
+
+    #[test]
+    fn train_generate_stats() {
+        let model = env::temp_dir().join(format!("markov-cli-train-{}.model", process::id()));
+        let model = model.to_str().unwrap();
+        let trained = run(&args(&format!("markov train test -o {} -r 2", model))).unwrap();
+        assert!(trained[0].starts_with("Saved a chain of "));
+        assert_eq!(run(&args(&format!("markov generate {} -n 3", model))).unwrap().len(), 3);
+        let stats = run(&args(&format!("markov stats {}", model))).unwrap();
+        assert_eq!(stats[0], "order: 2");
+        let json = env::temp_dir().join(format!("markov-cli-train-{}.json", process::id()));
+        let json = json.to_str().unwrap();
+        run(&args(&format!("markov convert {} {}", model, json))).unwrap();
+        assert!(fs::read_to_string(json).unwrap().starts_with("{\n  \"order\": 2,"));
+        fs::remove_file(json).unwrap();
+        fs::remove_file(model).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(run(&args("markov train test")),
+                   Err("Expected a model file to save to, given by -o.".to_owned()));
+        assert_eq!(run(&args("markov train test -o x -r 0")),
+                   Err("Expected positive integer argument to --order, found 0.".to_owned()));
+        assert_eq!(run(&args("markov frobnicate")),
+                   Err("Unknown command frobnicate, try --help.".to_owned()));
+        assert_eq!(run(&args("markov convert only.model")),
+                   Err("Expected a model file to convert and a file to write.".to_owned()));
+        let missing = run(&args("markov stats missing.model"));
+        assert!(missing.unwrap_err().starts_with("Failed to open missing.model"));
+        let corpus = env::temp_dir().join(format!("markov-cli-binary-{}.txt", process::id()));
+        fs::write(&corpus, b"I like\n\xff cats\n").unwrap();
+        let corpus = corpus.to_str().unwrap();
+        let binary = run(&args(&format!("markov train {} -o unused.model", corpus)));
+        assert!(binary.unwrap_err().starts_with(&format!("Failed to read {}", corpus)));
+        fs::remove_file(corpus).unwrap();
+    }
+}
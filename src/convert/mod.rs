@@ -0,0 +1,85 @@
+//! Conversion of saved chains between the formats supported by this crate: the text format
+//! written by `save`, and the JSON document written by `to_json`.
+//!
+//! # Examples
+//!
+//! ```
+//! use markov::Chain;
+//! use markov::convert::{Format, convert};
+//!
+//! let mut chain = Chain::new();
+//! chain.feed_str("I like cats");
+//! let mut saved = Vec::new();
+//! chain.save(&mut saved).unwrap();
+//! let mut json = Vec::new();
+//! convert(&saved[..], Format::Text, &mut json, Format::Json).unwrap();
+//! let converted: Chain<String> = Chain::from_json(&json[..]).unwrap();
+//! assert_eq!(converted.to_counts(), chain.to_counts());
+//! ```
+
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use Chain;
+
+/// A format in which a chain can be saved.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// The text format written by `save` and read by `load`.
+    Text,
+    /// The JSON document written by `to_json` and read by `from_json`.
+    Json,
+}
+
+impl Format {
+    /// Guesses the format of a file from its extension: JSON for `.json`, and text otherwise.
+    pub fn from_path<Q: AsRef<Path>>(path: Q) -> Format {
+        match path.as_ref().extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// Reads a chain saved in one format and writes it in another. Tokens are carried over as their
+/// text, so chains of any token type whose `Display` and `FromStr` implementations agree can be
+/// converted. As neither format holds the settings of a chain, there are none to carry over. This
+/// fails with an error of kind `InvalidData` if the input is malformed.
+pub fn convert<R, W>(reader: R, from: Format, writer: &mut W, to: Format) -> io::Result<()>
+    where R: BufRead, W: Write {
+    let chain: Chain<String> = match from {
+        Format::Text => Chain::load(reader)?,
+        Format::Json => Chain::from_json(reader)?,
+    };
+    match to {
+        Format::Text => chain.save(writer),
+        Format::Json => chain.to_json(writer),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use Chain;
+    use super::{Format, convert};
+
+    #[test]
+    fn round_trip() {
+        let mut chain = Chain::new();
+        chain.order(2).feed_str("I like cats").feed_str("I like\tdogs");
+        let mut saved = Vec::new();
+        chain.save(&mut saved).unwrap();
+        let mut json = Vec::new();
+        convert(&saved[..], Format::Text, &mut json, Format::Json).unwrap();
+        let mut text = Vec::new();
+        convert(&json[..], Format::Json, &mut text, Format::Text).unwrap();
+        assert_eq!(Chain::<String>::load(&text[..]).unwrap().to_counts(), chain.to_counts());
+        assert!(convert(&b"{}"[..], Format::Json, &mut Vec::new(), Format::Text).is_err());
+    }
+
+    #[test]
+    fn from_path() {
+        assert_eq!(Format::from_path("model.JSON"), Format::Json);
+        assert_eq!(Format::from_path("model.chain"), Format::Text);
+        assert_eq!(Format::from_path("model"), Format::Text);
+    }
+}
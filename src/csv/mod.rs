@@ -0,0 +1,117 @@
+//! The CSV format in which transitions are exported for analysis in other tools. The first row
+//! names the columns: one `context_N` column for each token of the state, then `next` and
+//! `count`. Each following row holds a single transition. The padding at the start of a
+//! collection and the end of a collection are written as empty fields, and fields are quoted as
+//! described in RFC 4180 when they need to be.
+//!
+//! Tables in the same dialect can also be read one record at a time, to feed text from a column.
+
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+use std::mem;
+
+use Edge;
+
+/// Writes a header for a chain of the given order and then the given transitions, as yielded by
+/// `edges`.
+pub(crate) fn write<'a, T, I, W>(order: usize, edges: I, writer: &mut W) -> io::Result<()>
+    where T: Display + 'a, I: Iterator<Item = Edge<'a, T>>, W: Write {
+    for i in 1..=order {
+        write!(writer, "context_{},", i)?;
+    }
+    write!(writer, "next,count\r\n")?;
+    for (state, token, count) in edges {
+        for token in state {
+            write!(writer, "{},", field(token))?;
+        }
+        write!(writer, "{},{}\r\n", field(token), count)?;
+    }
+    Ok(())
+}
+
+/// Formats a token, or the lack of one, as a field, quoting it if it holds a comma, a quote or a
+/// line break.
+fn field<T: Display>(token: Option<&T>) -> String {
+    let token = match token {
+        Some(token) => token.to_string(),
+        None => return String::new(),
+    };
+    if token.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", token.replace('"', "\"\""))
+    } else {
+        token
+    }
+}
+
+/// Reads the records of a CSV table one at a time. Quoted fields may hold commas, doubled quotes
+/// and line breaks, and records may end with either CRLF or LF.
+pub(crate) struct Reader<R> {
+    reader: R,
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Starts reading a table.
+    pub(crate) fn new(reader: R) -> Reader<R> {
+        Reader { reader }
+    }
+
+    /// Reads the fields of the next record, or `None` at the end of the table.
+    pub(crate) fn read_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 { return Ok(None) }
+        let (mut fields, mut field) = (Vec::new(), String::new());
+        let (mut quoted, mut start) = (false, true);
+        loop {
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if quoted => if chars.peek() == Some(&'"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        quoted = false;
+                    },
+                    '"' if start => quoted = true,
+                    ',' if !quoted => {
+                        fields.push(mem::take(&mut field));
+                        start = true;
+                        continue
+                    },
+                    '\r' | '\n' if !quoted => {},
+                    c => field.push(c),
+                }
+                start = false;
+            }
+            if !quoted { break }
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "Found an unterminated quoted field."))
+            }
+        }
+        fields.push(field);
+        Ok(Some(fields))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Reader, field};
+
+    #[test]
+    fn quoting() {
+        assert_eq!(field(Some(&"plain")), "plain");
+        assert_eq!(field(Some(&"a,b")), "\"a,b\"");
+        assert_eq!(field(Some(&"say \"hi\"")), "\"say \"\"hi\"\"\"");
+        assert_eq!(field::<String>(None), "");
+    }
+
+    #[test]
+    fn records() {
+        let mut reader = Reader::new(&b"a,\"b,\"\"c\"\"\",\r\n\"two\r\nlines\",x\n"[..]);
+        assert_eq!(reader.read_record().unwrap().unwrap(), ["a", "b,\"c\"", ""]);
+        assert_eq!(reader.read_record().unwrap().unwrap(), ["two\r\nlines", "x"]);
+        assert_eq!(reader.read_record().unwrap(), None);
+        assert!(Reader::new(&b"\"open"[..]).read_record().is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Export of chains as [Graphviz](https://graphviz.org) DOT graphs.
+//!
+//! # Examples
+//!
+//! ```
+//! use markov::Chain;
+//! use markov::dot::DotOptions;
+//!
+//! let mut chain = Chain::new();
+//! chain.feed_str("I like cats and I like dogs");
+//! let mut dot = Vec::new();
+//! chain.to_dot(&mut dot, DotOptions { min_count: 2, around: None }).unwrap();
+//! assert!(String::from_utf8(dot).unwrap().contains("label=\"2\""));
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io::{self, Write};
+
+use Edge;
+
+/// Options controlling which transitions are written by `to_dot`.
+#[derive(PartialEq, Eq, Debug)]
+pub struct DotOptions<'a, T: 'a> {
+    /// The minimum number of times a transition must have been observed to be written.
+    pub min_count: usize,
+    /// If set, only transitions from a state containing this token, or to this token, are
+    /// written.
+    pub around: Option<&'a T>,
+}
+
+impl<'a, T> Clone for DotOptions<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for DotOptions<'a, T> {}
+
+impl<'a, T> Default for DotOptions<'a, T> {
+    fn default() -> Self {
+        DotOptions { min_count: 1, around: None }
+    }
+}
+
+/// A state as written to the graph, or `None` for the end of a collection.
+type Node<'a, T> = Option<Vec<Option<&'a T>>>;
+
+/// Writes the given transitions, as yielded by `edges`, as a DOT graph.
+pub(crate) fn write<'a, T, I, W>(edges: I, options: DotOptions<T>, writer: &mut W)
+    -> io::Result<()>
+    where T: Display + Eq + Hash + 'a, W: Write,
+          I: Iterator<Item = Edge<'a, T>> {
+    let mut nodes = HashMap::new();
+    writeln!(writer, "digraph chain {{")?;
+    for (state, token, count) in edges {
+        if count < options.min_count { continue }
+        if let Some(around) = options.around {
+            if !state.contains(&Some(around)) && token != Some(around) { continue }
+        }
+        let next = token.map(|token| {
+            let mut next = state.clone();
+            if !next.is_empty() {
+                next.remove(0);
+                next.push(Some(token));
+            }
+            next
+        });
+        let from = node(&mut nodes, Some(state), writer)?;
+        let to = node(&mut nodes, next, writer)?;
+        writeln!(writer, "    n{} -> n{} [label=\"{}\"];", from, to, count)?;
+    }
+    writeln!(writer, "}}")
+}
+
+/// Gets the identifier of the node, writing its declaration the first time it is seen.
+fn node<'a, T, W>(nodes: &mut HashMap<Node<'a, T>, usize>, node: Node<'a, T>, writer: &mut W)
+    -> io::Result<usize> where T: Display + Eq + Hash, W: Write {
+    if let Some(&id) = nodes.get(&node) { return Ok(id) }
+    let id = nodes.len();
+    let label = match node {
+        Some(ref state) => state.iter().map(|token| match *token {
+            Some(token) => escape(token),
+            None => "(start)".to_owned(),
+        }).collect::<Vec<_>>().join(" "),
+        None => "(end)".to_owned(),
+    };
+    writeln!(writer, "    n{} [label=\"{}\"];", id, label)?;
+    nodes.insert(node, id);
+    Ok(id)
+}
+
+/// Formats the token for use within a quoted DOT string.
+fn escape<T: Display>(token: &T) -> String {
+    token.to_string().replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use Chain;
+    use super::DotOptions;
+
+    fn dot(chain: &Chain<String>, options: DotOptions<String>) -> String {
+        let mut dot = Vec::new();
+        chain.to_dot(&mut dot, options).unwrap();
+        String::from_utf8(dot).unwrap()
+    }
+
+    #[test]
+    fn write_graph() {
+        let mut chain = Chain::new();
+        chain.feed_str("say \"hi\"");
+        let dot = dot(&chain, DotOptions::default());
+        assert!(dot.starts_with("digraph chain {\n") && dot.ends_with("}\n"));
+        assert_eq!(dot.matches(" -> ").count(), 3);
+        assert!(dot.contains("[label=\"(start)\"]") && dot.contains("[label=\"(end)\"]"));
+        assert!(dot.contains("[label=\"\\\"hi\\\"\"]"));
+    }
+
+    #[test]
+    fn filter() {
+        let mut chain = Chain::new();
+        chain.feed_str("a b c").feed_str("a b");
+        let b = "b".to_owned();
+        let edges = |options| dot(&chain, options).matches(" -> ").count();
+        assert_eq!(edges(DotOptions { min_count: 2, around: None }), 2);
+        assert_eq!(edges(DotOptions { min_count: 1, around: Some(&b) }), 3);
+    }
+
+    #[test]
+    fn sorted() {
+        let sorted = || {
+            let mut chain = Chain::new();
+            chain.feed_str("a b c d e f").feed_str("f e d c b a");
+            let mut dot = Vec::new();
+            chain.to_dot_sorted(&mut dot, DotOptions::default()).unwrap();
+            String::from_utf8(dot).unwrap()
+        };
+        let first = sorted();
+        assert!((0..10).all(|_| sorted() == first));
+        assert!(first.starts_with("digraph chain {\n    n0 [label=\"(start)\"];\n"));
+    }
+}
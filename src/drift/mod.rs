@@ -0,0 +1,183 @@
+//! Monitoring of how a continuously trained chain drifts away from an earlier snapshot of itself.
+//!
+//! # Examples
+//!
+//! ```
+//! use markov::Chain;
+//! use markov::drift::{DriftMonitor, DriftThresholds};
+//!
+//! let mut chain = Chain::new();
+//! chain.feed_str("I like cats and I like dogs");
+//! let monitor = DriftMonitor::new(chain.profile(3), DriftThresholds::default());
+//! chain.feed_str("buy cheap pills now at this totally legitimate website");
+//! for event in monitor.check(&chain.profile(3)) {
+//!     println!("{:?}", event);
+//! }
+//! ```
+
+use std::cmp::Reverse;
+
+/// A summary of the vocabulary learned by a chain, used to detect drift.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Profile<T> {
+    /// The number of distinct tokens fed into the chain.
+    pub vocabulary: usize,
+    /// The Shannon entropy of the token frequency distribution, in bits.
+    pub entropy: f64,
+    /// The most frequent tokens, most frequent first.
+    pub top_tokens: Vec<T>,
+}
+
+impl<T> Profile<T> {
+    /// Builds a profile from the number of times each token was fed, keeping the given number of
+    /// most frequent tokens.
+    pub(crate) fn from_counts<'a, I>(counts: I, top: usize) -> Profile<T>
+        where I: IntoIterator<Item = (&'a T, usize)>, T: Clone + 'a {
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        let total: f64 = counts.iter().map(|&(_, count)| count as f64).sum();
+        let entropy = counts.iter().filter(|&&(_, count)| count > 0).map(|&(_, count)| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        }).sum();
+        counts.sort_by_key(|&(_, count)| Reverse(count));
+        Profile {
+            vocabulary: counts.len(),
+            entropy,
+            top_tokens: counts.into_iter().take(top).map(|(token, _)| token.clone()).collect(),
+        }
+    }
+}
+
+/// The amount of drift tolerated before a `DriftMonitor` reports an event.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DriftThresholds {
+    /// The largest tolerated growth in vocabulary, relative to the baseline, e.g. `0.5` for 50%.
+    pub vocabulary_growth: f64,
+    /// The largest tolerated change in entropy, in bits.
+    pub entropy_change: f64,
+    /// The smallest tolerated fraction of the baseline's top tokens that are still top tokens.
+    pub top_token_overlap: f64,
+}
+
+impl Default for DriftThresholds {
+    fn default() -> DriftThresholds {
+        DriftThresholds { vocabulary_growth: 0.5, entropy_change: 1.0, top_token_overlap: 0.5 }
+    }
+}
+
+/// A kind of drift that exceeded its threshold.
+#[derive(Clone, PartialEq, Debug)]
+pub enum DriftEvent {
+    /// The vocabulary grew by more than the tolerated fraction.
+    VocabularyGrowth {
+        /// The vocabulary size of the baseline.
+        baseline: usize,
+        /// The current vocabulary size.
+        current: usize,
+    },
+    /// The entropy changed by more than the tolerated number of bits.
+    EntropyShift {
+        /// The entropy of the baseline.
+        baseline: f64,
+        /// The current entropy.
+        current: f64,
+    },
+    /// Too few of the baseline's top tokens remain among the current top tokens.
+    TopTokenChurn {
+        /// The fraction of the baseline's top tokens still among the current top tokens.
+        overlap: f64,
+    },
+}
+
+/// Compares profiles of a chain against a baseline profile, reporting drift that exceeds the
+/// configured thresholds. Operators of continuously learning chains can call `check` with a fresh
+/// profile periodically to notice poisoning or topic shifts early.
+#[derive(Clone, Debug)]
+pub struct DriftMonitor<T> {
+    baseline: Profile<T>,
+    thresholds: DriftThresholds,
+}
+
+impl<T> DriftMonitor<T> where T: PartialEq {
+    /// Constructs a monitor comparing against the given baseline profile.
+    pub fn new(baseline: Profile<T>, thresholds: DriftThresholds) -> DriftMonitor<T> {
+        DriftMonitor { baseline, thresholds }
+    }
+
+    /// Gets the baseline profile.
+    pub fn baseline(&self) -> &Profile<T> {
+        &self.baseline
+    }
+
+    /// Replaces the baseline profile, e.g. after drift has been reviewed and accepted.
+    pub fn rebaseline(&mut self, baseline: Profile<T>) {
+        self.baseline = baseline;
+    }
+
+    /// Compares the current profile against the baseline, returning every kind of drift that
+    /// exceeds its threshold.
+    pub fn check(&self, current: &Profile<T>) -> Vec<DriftEvent> {
+        let mut events = Vec::new();
+        let baseline = &self.baseline;
+        let growth = (current.vocabulary as f64 - baseline.vocabulary as f64) /
+                     (baseline.vocabulary.max(1) as f64);
+        if growth > self.thresholds.vocabulary_growth {
+            events.push(DriftEvent::VocabularyGrowth {
+                baseline: baseline.vocabulary,
+                current: current.vocabulary,
+            });
+        }
+        if (current.entropy - baseline.entropy).abs() > self.thresholds.entropy_change {
+            events.push(DriftEvent::EntropyShift {
+                baseline: baseline.entropy,
+                current: current.entropy,
+            });
+        }
+        if !baseline.top_tokens.is_empty() {
+            let kept = baseline.top_tokens.iter()
+                               .filter(|token| current.top_tokens.contains(token)).count();
+            let overlap = kept as f64 / baseline.top_tokens.len() as f64;
+            if overlap < self.thresholds.top_token_overlap {
+                events.push(DriftEvent::TopTokenChurn { overlap });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DriftEvent, DriftMonitor, DriftThresholds, Profile};
+    use Chain;
+
+    #[test]
+    fn profile() {
+        let mut chain = Chain::new();
+        chain.feed_str("a a b c");
+        let profile = chain.profile(1);
+        assert_eq!(profile.vocabulary, 3);
+        assert_eq!(profile.entropy, 1.5);
+        assert_eq!(profile.top_tokens, vec!["a".to_owned()]);
+    }
+
+    #[test]
+    fn no_drift() {
+        let mut chain = Chain::new();
+        chain.feed_str("I like cats and I like dogs");
+        let monitor = DriftMonitor::new(chain.profile(2), DriftThresholds::default());
+        chain.feed_str("I like cats");
+        assert_eq!(monitor.check(&chain.profile(2)), vec![]);
+    }
+
+    #[test]
+    fn drift() {
+        let baseline = Profile { vocabulary: 2, entropy: 1.0, top_tokens: vec!["a", "b"] };
+        let current = Profile { vocabulary: 4, entropy: 2.5, top_tokens: vec!["c", "d"] };
+        let monitor = DriftMonitor::new(baseline, DriftThresholds::default());
+        assert_eq!(monitor.check(&current), vec![
+            DriftEvent::VocabularyGrowth { baseline: 2, current: 4 },
+            DriftEvent::EntropyShift { baseline: 1.0, current: 2.5 },
+            DriftEvent::TopTokenChurn { overlap: 0.0 },
+        ]);
+    }
+}
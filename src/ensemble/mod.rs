@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use rand::{Rng, thread_rng};
+
+use {Chain, Chainable, Token, sum_counts};
+use tokenizer::{Detokenizer, WhitespaceDetokenizer};
+
+/// A mixture of several chains, which may differ in order or in what they were fed. At each step
+/// of generation, the next token is drawn from the weighted average of the members' distributions
+/// over continuations of the tokens generated so far. Members for which those tokens form an
+/// unseen state sit the step out, and the remaining weights are renormalized.
+#[derive(Debug)]
+pub struct EnsembleChain<T> where T: Chainable {
+    members: Vec<(Chain<T>, f64)>,
+}
+
+impl<T> EnsembleChain<T> where T: Chainable {
+    /// Constructs an ensemble with no members.
+    pub fn new() -> EnsembleChain<T> {
+        EnsembleChain { members: Vec::new() }
+    }
+
+    /// Adds a chain to the ensemble with the given mixing weight. Only the ratios between weights
+    /// matter, so they need not sum to one.
+    pub fn add(&mut self, chain: Chain<T>, weight: f64) -> &mut EnsembleChain<T> {
+        self.members.push((chain, weight));
+        self
+    }
+
+    /// Gets the members of the ensemble along with their weights.
+    pub fn members(&self) -> &[(Chain<T>, f64)] {
+        &self.members
+    }
+
+    /// Generates a collection of tokens from the ensemble.
+    pub fn generate(&self) -> Vec<Rc<T>> {
+        self.generate_with_rng(&mut thread_rng())
+    }
+
+    /// Generates a collection of tokens from the ensemble using the given random number generator.
+    pub fn generate_with_rng<R: Rng>(&self, rng: &mut R) -> Vec<Rc<T>> {
+        let mut ret = Vec::new();
+        while let Some(next) = self.next(&ret, rng) {
+            ret.push(next);
+        }
+        ret
+    }
+
+    /// Draws the token following the given tokens from the mixture of the members' distributions.
+    fn next<R: Rng>(&self, history: &[Rc<T>], rng: &mut R) -> Token<Rc<T>> {
+        let mut mixture: HashMap<&Token<Rc<T>>, f64> = HashMap::new();
+        let mut total = 0.0;
+        for &(ref chain, weight) in &self.members {
+            let start = history.len().saturating_sub(chain.order);
+            let mut state = vec!(None; chain.order - (history.len() - start));
+            state.extend(history[start..].iter().cloned().map(Some));
+            let states = match chain.map.get(&state) {
+                Some(states) => states,
+                None => continue,
+            };
+            let count = sum_counts(states.values()) as f64;
+            if count == 0.0 { continue }
+            for (token, &value) in states {
+                *mixture.entry(token).or_insert(0.0) += weight * value as f64 / count;
+            }
+            total += weight;
+        }
+        if total <= 0.0 { return None }
+        let mut cap = rng.gen::<f64>() * total;
+        let mut last = None;
+        for (token, probability) in mixture {
+            if cap < probability {
+                return token.clone()
+            }
+            cap -= probability;
+            last = token.clone();
+        }
+        last
+    }
+}
+
+impl<T> Default for EnsembleChain<T> where T: Chainable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnsembleChain<String> {
+    /// Generates a random string of text.
+    pub fn generate_str(&self) -> String {
+        let tokens = self.generate();
+        let tokens: Vec<_> = tokens.iter().map(|s| &s[..]).collect();
+        WhitespaceDetokenizer.detokenize(&tokens)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+    use Chain;
+    use super::EnsembleChain;
+
+    fn chain(order: usize, tokens: Vec<u8>) -> Chain<u8> {
+        let mut chain = Chain::new();
+        chain.order(order).feed(tokens);
+        chain
+    }
+
+    #[test]
+    fn mix_members() {
+        let mut ensemble = EnsembleChain::new();
+        ensemble.add(chain(2, vec![1, 2, 3]), 1.0).add(chain(1, vec![1, 4]), 0.0);
+        let expected: Vec<_> = vec![1, 2, 3].into_iter().map(Rc::new).collect();
+        assert!((0..20).all(|_| ensemble.generate() == expected));
+        let mut ensemble = EnsembleChain::new();
+        ensemble.add(chain(2, vec![1, 2, 3]), 1.0).add(chain(1, vec![1, 4]), 1.0);
+        let blended: Vec<_> = vec![1, 4].into_iter().map(Rc::new).collect();
+        assert!((0..100).any(|_| ensemble.generate() == blended));
+    }
+
+    #[test]
+    fn empty() {
+        assert!(EnsembleChain::<u8>::new().generate().is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+//! Evaluation of chain configurations against held-out data.
+//!
+//! # Examples
+//!
+//! ```
+//! use markov::eval::{EvalConfig, cross_validate};
+//!
+//! let corpus: Vec<Vec<_>> = ["I like cats", "I like dogs", "you like cats", "I hate dogs"]
+//!     .iter().map(|line| line.split(' ').collect()).collect();
+//! let configs = [EvalConfig { order: 1, smoothing: 0.5, prune: 0 },
+//!                EvalConfig { order: 2, smoothing: 0.5, prune: 0 },
+//!                EvalConfig { order: 2, smoothing: 0.5, prune: 2 }];
+//! for result in cross_validate(&corpus, 2, &configs) {
+//!     println!("{:?}: {} ({} bytes)", result.config, result.perplexity, result.memory);
+//! }
+//! ```
+
+use {Chain, Chainable};
+
+/// A candidate configuration of a chain.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EvalConfig {
+    /// The order of the chain.
+    pub order: usize,
+    /// The pseudo-count added to every possible continuation of every state when evaluating, so
+    /// that continuations never observed in training are not impossible. Without smoothing, the
+    /// perplexity of held-out data is usually infinite.
+    pub smoothing: f64,
+    /// The number of times a transition must have been observed to be kept. Rarer transitions are
+    /// removed from the trained chains before evaluating, along with any states left without
+    /// continuations, trading accuracy for memory. Zero and one keep every transition.
+    pub prune: usize,
+}
+
+/// The outcome of evaluating a configuration.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EvalResult {
+    /// The evaluated configuration.
+    pub config: EvalConfig,
+    /// The perplexity of the held-out data, over all folds. Lower is better.
+    pub perplexity: f64,
+    /// The average number of states in the trained chains.
+    pub states: usize,
+    /// The average number of distinct transitions in the trained chains, which dominates their
+    /// memory usage.
+    pub transitions: usize,
+    /// The average approximate number of bytes used by the trained chains, as reported by
+    /// `stats`.
+    pub memory: usize,
+}
+
+/// Evaluates each configuration using k-fold cross-validation. The corpus is split into the given
+/// number of folds, and for each fold a chain is trained on the remaining folds and evaluated on
+/// it. This panics if there are fewer than two folds, or more folds than sequences in the corpus.
+pub fn cross_validate<T>(corpus: &[Vec<T>], folds: usize, configs: &[EvalConfig])
+    -> Vec<EvalResult> where T: Chainable + Clone {
+    assert!(folds >= 2 && folds <= corpus.len(),
+            "Expected between 2 and {} folds, found {}.", corpus.len(), folds);
+    configs.iter().map(|&config| {
+        let (mut log_prob, mut tokens) = (0.0, 0);
+        let (mut states, mut transitions, mut memory) = (0, 0, 0);
+        for fold in 0..folds {
+            let mut chain = Chain::new();
+            chain.order(config.order);
+            chain.feed_all(corpus.iter().enumerate().filter(|&(i, _)| i % folds != fold)
+                                 .map(|(_, tokens)| tokens.iter().cloned()));
+            chain.map.retain(|_, states| {
+                states.retain(|_, &mut count| count >= config.prune);
+                !states.is_empty()
+            });
+            for held_out in corpus.iter().skip(fold).step_by(folds) {
+                log_prob += chain.score_smoothed(held_out, config.smoothing);
+                tokens += held_out.len() + 1;
+            }
+            let stats = chain.stats();
+            states += stats.states;
+            transitions += stats.transitions;
+            memory += stats.memory;
+        }
+        EvalResult {
+            config,
+            perplexity: (-log_prob / tokens as f64).exp(),
+            states: states / folds,
+            transitions: transitions / folds,
+            memory: memory / folds,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EvalConfig, cross_validate};
+
+    fn config(smoothing: f64, prune: usize) -> EvalConfig {
+        EvalConfig { order: 1, smoothing, prune }
+    }
+
+    #[test]
+    fn deterministic_corpus() {
+        let corpus = vec![vec![1u8, 2, 3]; 4];
+        let results = cross_validate(&corpus, 2, &[config(0.0, 0), config(1.0, 0)]);
+        assert_eq!(results[0].perplexity, 1.0);
+        assert!(results[1].perplexity > 1.0);
+        assert_eq!((results[0].states, results[0].transitions), (4, 4));
+    }
+
+    #[test]
+    fn prune_rare_transitions() {
+        let mut corpus = vec![vec![1u8, 2]; 6];
+        corpus.push(vec![1, 3]);
+        corpus.push(vec![1, 4]);
+        let results = cross_validate(&corpus, 2, &[config(0.5, 0), config(0.5, 2)]);
+        assert_eq!((results[0].states, results[0].transitions), (4, 5));
+        assert_eq!((results[1].states, results[1].transitions), (3, 3));
+        assert!(results[1].memory < results[0].memory);
+    }
+
+    #[test]
+    fn unseen_transitions() {
+        let corpus = vec![vec![1u8, 2], vec![1, 3]];
+        let results = cross_validate(&corpus, 2, &[config(0.0, 0), config(0.5, 0)]);
+        assert_eq!(results[0].perplexity, f64::INFINITY);
+        assert!(results[1].perplexity.is_finite());
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected between 2 and 2 folds, found 3.")]
+    fn too_many_folds() {
+        cross_validate(&[vec![1u8], vec![2]], 3, &[config(0.0, 0)]);
+    }
+}
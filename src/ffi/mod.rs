@@ -0,0 +1,103 @@
+//! A C API for chains of strings, for embedding the crate in other languages. This is only
+//! available with the `ffi` feature enabled.
+//!
+//! A chain is created with `markov_chain_new`, which returns an opaque handle that must be
+//! released with `markov_chain_free`. Strings passed in must be valid, nul-terminated UTF-8, and
+//! strings returned by `markov_chain_generate_str` are owned by the caller, who must release them
+//! with `markov_string_free`. As the handle wraps an `ArcChain`, a chain may be generated from by
+//! several threads at once, but it must not be fed while it is being used by any other thread.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+use ArcChain;
+
+/// Constructs a new chain of strings of the given order.
+#[no_mangle]
+pub extern "C" fn markov_chain_new(order: usize) -> *mut ArcChain<String> {
+    let mut chain = ArcChain::new();
+    chain.order(order);
+    Box::into_raw(Box::new(chain))
+}
+
+/// Releases a chain constructed by `markov_chain_new`. Passing a null pointer does nothing.
+///
+/// # Safety
+///
+/// The chain must have been returned by `markov_chain_new`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn markov_chain_free(chain: *mut ArcChain<String>) {
+    if !chain.is_null() {
+        drop(Box::from_raw(chain));
+    }
+}
+
+/// Feeds a string of text into the chain, splitting it on whitespace. This returns false, leaving
+/// the chain unchanged, if either pointer is null or the text is not valid UTF-8.
+///
+/// # Safety
+///
+/// The chain must be a live handle that no other thread is using, and the text must be a
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn markov_chain_feed_str(chain: *mut ArcChain<String>, text: *const c_char)
+    -> bool {
+    if chain.is_null() || text.is_null() { return false }
+    match CStr::from_ptr(text).to_str() {
+        Ok(text) => {
+            (*chain).feed_str(text);
+            true
+        },
+        Err(_) => false,
+    }
+}
+
+/// Generates a random string of text from the chain. This returns null if the chain is null or
+/// if the generated text contains a nul byte.
+///
+/// # Safety
+///
+/// The chain must be a live handle that is not being fed by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn markov_chain_generate_str(chain: *const ArcChain<String>)
+    -> *mut c_char {
+    if chain.is_null() { return ptr::null_mut() }
+    CString::new((*chain).generate_str()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Releases a string returned by `markov_chain_generate_str`. Passing a null pointer does
+/// nothing.
+///
+/// # Safety
+///
+/// The string must have been returned by `markov_chain_generate_str`, and must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn markov_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        unsafe {
+            let chain = markov_chain_new(1);
+            let text = CString::new("I like cats").unwrap();
+            assert!(markov_chain_feed_str(chain, text.as_ptr()));
+            assert!(!markov_chain_feed_str(chain, ptr::null()));
+            let generated = markov_chain_generate_str(chain);
+            assert_eq!(CStr::from_ptr(generated).to_str(), Ok("I like cats"));
+            markov_string_free(generated);
+            markov_chain_free(chain);
+            assert!(markov_chain_generate_str(ptr::null()).is_null());
+        }
+    }
+}
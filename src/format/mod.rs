@@ -0,0 +1,153 @@
+//! The text format in which chains are saved. The first line holds the magic string `markov` and
+//! the version of the format, the second line holds the order of the chain, and each following
+//! line holds a single transition as tab-separated fields: the number of times it was observed,
+//! the token that followed the state, and then the tokens of the state. Tokens are written with
+//! `Display` and read back with `FromStr`, with backslashes, tabs and line breaks escaped, and
+//! with `\-` standing for the padding at the start of a collection or for its end.
+//!
+//! Files written before the header was introduced, which start directly with the order, are read
+//! as version 0. Any change to the format must increment `VERSION`, and `Reader` must keep reading
+//! every earlier version.
+
+use std::io::{self, BufRead, Lines, Write};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use Edge;
+
+/// The magic string that starts every saved chain.
+const MAGIC: &str = "markov";
+
+/// The version of the format written by `write`.
+const VERSION: u32 = 1;
+
+/// A transition read back from a saved chain.
+pub(crate) type OwnedEdge<T> = (Vec<Option<T>>, Option<T>, usize);
+
+/// Writes the order of a chain and the given transitions, as yielded by `edges`.
+pub(crate) fn write<'a, T, I, W>(order: usize, edges: I, writer: &mut W) -> io::Result<()>
+    where T: Display + 'a, I: Iterator<Item = Edge<'a, T>>, W: Write {
+    writeln!(writer, "{} {}", MAGIC, VERSION)?;
+    writeln!(writer, "{}", order)?;
+    for (state, token, count) in edges {
+        write!(writer, "{}\t{}", count, escape(token))?;
+        for token in state {
+            write!(writer, "\t{}", escape(token))?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Reads the transitions of a saved chain one at a time.
+pub(crate) struct Reader<R> {
+    lines: Lines<R>,
+    order: usize,
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Starts reading a saved chain, reading its header and order. This fails if the chain was
+    /// saved in a newer version of the format.
+    pub(crate) fn new(reader: R) -> io::Result<Reader<R>> {
+        let mut lines = reader.lines();
+        let mut line = match lines.next() {
+            Some(line) => line?,
+            None => return Err(invalid("Expected a saved chain, found an empty file.")),
+        };
+        if let Some(version) = line.strip_prefix(MAGIC) {
+            let version: u32 = version.trim().parse()
+                                      .map_err(|_| invalid("Expected the format's version."))?;
+            if version > VERSION {
+                return Err(invalid(&format!("Found version {} of the format, which is newer than \
+                                             the latest supported version, {}.",
+                                            version, VERSION)))
+            }
+            line = lines.next().ok_or_else(|| invalid("Expected the chain's order."))??;
+        }
+        let order = line.trim().parse().map_err(|_| invalid("Expected the chain's order."))?;
+        Ok(Reader { lines, order })
+    }
+
+    /// Gets the order of the saved chain.
+    pub(crate) fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Reads the next transition, or `None` at the end of the file.
+    pub(crate) fn read_edge<T: FromStr>(&mut self) -> io::Result<Option<OwnedEdge<T>>> {
+        let line = match self.lines.next() {
+            Some(line) => line?,
+            None => return Ok(None),
+        };
+        let mut fields = line.split('\t');
+        let count = fields.next().and_then(|count| count.parse().ok())
+                          .ok_or_else(|| invalid("Expected the number of observations."))?;
+        let token = unescape(fields.next().ok_or_else(|| invalid("Expected a token."))?)?;
+        let state = fields.map(unescape).collect::<io::Result<Vec<_>>>()?;
+        if state.len() != self.order {
+            return Err(invalid("Expected a state of as many tokens as the chain's order."))
+        }
+        Ok(Some((state, token, count)))
+    }
+}
+
+/// Escapes a token, or the lack of one, for use as a field.
+pub(crate) fn escape<T: Display>(token: Option<&T>) -> String {
+    match token {
+        Some(token) => token.to_string().replace('\\', "\\\\").replace('\t', "\\t")
+                            .replace('\n', "\\n").replace('\r', "\\r"),
+        None => "\\-".to_owned(),
+    }
+}
+
+/// Parses a field written by `escape`.
+pub(crate) fn unescape<T: FromStr>(field: &str) -> io::Result<Option<T>> {
+    if field == "\\-" { return Ok(None) }
+    let mut token = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        token.push(match c {
+            '\\' => match chars.next() {
+                Some('\\') => '\\',
+                Some('t') => '\t',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                _ => return Err(invalid("Found an unknown escape sequence.")),
+            },
+            c => c,
+        });
+    }
+    token.parse().map(Some).map_err(|_| invalid("Found a token that could not be parsed."))
+}
+
+/// Constructs the error returned for malformed files.
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Reader, escape, unescape};
+
+    #[test]
+    fn versions() {
+        assert_eq!(Reader::new(&b"markov 1\n2\n"[..]).unwrap().order(), 2);
+        let mut legacy = Reader::new(&b"1\n3\tx\t\\-\n"[..]).unwrap();
+        assert_eq!(legacy.order(), 1);
+        assert_eq!(legacy.read_edge::<String>().unwrap(),
+                   Some((vec![None], Some("x".to_owned()), 3)));
+        let newer = Reader::new(&b"markov 99\n1\n"[..]).err().unwrap();
+        assert_eq!(newer.to_string(), "Found version 99 of the format, which is newer than the \
+                                       latest supported version, 1.");
+    }
+
+    #[test]
+    fn escaping() {
+        for token in &["plain", "tab\there", "back\\slash", "new\nline", "\\-"] {
+            let token = token.to_string();
+            assert_eq!(unescape::<String>(&escape(Some(&token))).unwrap(), Some(token));
+        }
+        assert_eq!(unescape::<String>(&escape::<String>(None)).unwrap(), None);
+        assert!(unescape::<String>("bad\\q").is_err());
+    }
+}
@@ -0,0 +1,350 @@
+//! The JSON format in which chains are exported for reading by people. The document holds the
+//! order of the chain and a list of its states, each with the tokens of the state (`null` standing
+//! for the padding at the start of a collection), the number of times each token followed it, and
+//! the number of times a collection ended after it, if any:
+//!
+//! ```json
+//! {
+//!   "order": 1,
+//!   "states": [
+//!     {"state": [null], "next": {"I": 1}},
+//!     {"state": ["I"], "next": {"like": 1}},
+//!     {"state": ["like"], "next": {}, "end": 1}
+//!   ]
+//! }
+//! ```
+//!
+//! States and tokens are sorted by their text, so that equal chains produce identical documents.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use Edge;
+use format::OwnedEdge;
+
+/// The number of times each token followed a state, and the number of times a collection ended
+/// after it.
+type Continuations = (BTreeMap<String, usize>, usize);
+
+/// Writes the order of a chain and the given transitions, as yielded by `edges`.
+pub(crate) fn write<'a, T, I, W>(order: usize, edges: I, writer: &mut W) -> io::Result<()>
+    where T: Display + 'a, I: Iterator<Item = Edge<'a, T>>, W: Write {
+    let mut states: BTreeMap<Vec<Option<String>>, Continuations> = BTreeMap::new();
+    for (state, token, count) in edges {
+        let state = state.iter().map(|token| token.map(|token| token.to_string())).collect();
+        let entry = states.entry(state).or_default();
+        match token {
+            Some(token) => { entry.0.insert(token.to_string(), count); },
+            None => entry.1 = count,
+        }
+    }
+    write!(writer, "{{\n  \"order\": {},\n  \"states\": [", order)?;
+    for (i, (state, (next, end))) in states.into_iter().enumerate() {
+        write!(writer, "{}\n    {{\"state\": [", if i == 0 { "" } else { "," })?;
+        for (j, token) in state.iter().enumerate() {
+            if j > 0 { write!(writer, ", ")?; }
+            match *token {
+                Some(ref token) => write_string(token, writer)?,
+                None => write!(writer, "null")?,
+            }
+        }
+        write!(writer, "], \"next\": {{")?;
+        for (j, (token, count)) in next.iter().enumerate() {
+            if j > 0 { write!(writer, ", ")?; }
+            write_string(token, writer)?;
+            write!(writer, ": {}", count)?;
+        }
+        write!(writer, "}}")?;
+        if end > 0 { write!(writer, ", \"end\": {}", end)?; }
+        write!(writer, "}}")?;
+    }
+    writeln!(writer, "\n  ]\n}}")
+}
+
+/// Writes a string as a JSON string literal.
+fn write_string<W: Write>(string: &str, writer: &mut W) -> io::Result<()> {
+    write!(writer, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(writer, "\\\"")?,
+            '\\' => write!(writer, "\\\\")?,
+            '\n' => write!(writer, "\\n")?,
+            '\r' => write!(writer, "\\r")?,
+            '\t' => write!(writer, "\\t")?,
+            c if c < ' ' => write!(writer, "\\u{:04x}", c as u32)?,
+            c => write!(writer, "{}", c)?,
+        }
+    }
+    write!(writer, "\"")
+}
+
+/// Reads a document written by `write`, returning the order of the chain and its transitions.
+pub(crate) fn read<T, R>(mut reader: R) -> io::Result<(usize, Vec<OwnedEdge<T>>)>
+    where T: FromStr, R: Read {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    let document = parse_document(&text)?;
+    let order = field(&document, "order").and_then(Value::count)
+                                         .ok_or_else(|| invalid("Expected the chain's order."))?;
+    let states = match field(&document, "states") {
+        Some(Value::Array(states)) => states,
+        _ => return Err(invalid("Expected a list of states.")),
+    };
+    let mut edges = Vec::new();
+    for entry in states {
+        let state = match field(entry, "state") {
+            Some(Value::Array(state)) => state.iter().map(|token| match *token {
+                Value::Null => Ok(None),
+                Value::String(ref token) => Ok(Some(&token[..])),
+                _ => Err(invalid("Expected a token or null.")),
+            }).collect::<io::Result<Vec<_>>>()?,
+            _ => return Err(invalid("Expected the tokens of a state.")),
+        };
+        if state.len() != order {
+            return Err(invalid("Expected a state of as many tokens as the chain's order."))
+        }
+        let parse_state = || -> io::Result<Vec<Option<T>>> {
+            state.iter().map(|token| token.map(parse).transpose()).collect()
+        };
+        match field(entry, "next") {
+            Some(Value::Object(next)) => for (token, count) in next {
+                let count = count.count().ok_or_else(|| invalid("Expected a count."))?;
+                edges.push((parse_state()?, Some(parse(token)?), count));
+            },
+            _ => return Err(invalid("Expected the tokens following a state.")),
+        }
+        if let Some(end) = field(entry, "end") {
+            let end = end.count().ok_or_else(|| invalid("Expected a count."))?;
+            edges.push((parse_state()?, None, end));
+        }
+    }
+    Ok((order, edges))
+}
+
+/// Gets the string held by the named field of a document holding a single object, such as a line
+/// of a JSON lines file, or `None` if the field is missing or null.
+pub(crate) fn string_field(text: &str, name: &str) -> io::Result<Option<String>> {
+    let document = parse_document(text)?;
+    if let Value::Object(_) = document {} else { return Err(invalid("Expected an object.")) }
+    match field(&document, name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(string)) => Ok(Some(string.clone())),
+        Some(_) => Err(invalid("Expected a string.")),
+    }
+}
+
+/// Parses a document holding a single value.
+fn parse_document(text: &str) -> io::Result<Value> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0 };
+    let document = parser.value()?;
+    parser.whitespace();
+    if parser.pos < parser.chars.len() { return Err(invalid("Found text after the document.")) }
+    Ok(document)
+}
+
+/// Parses a token written with `Display`.
+fn parse<T: FromStr>(token: &str) -> io::Result<T> {
+    token.parse().map_err(|_| invalid("Found a token that could not be parsed."))
+}
+
+/// Gets the named field of an object.
+fn field<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    match *value {
+        Value::Object(ref fields) => fields.iter().find(|(key, _)| key == name)
+                                           .map(|(_, value)| value),
+        _ => None,
+    }
+}
+
+/// Constructs the error returned for malformed documents.
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A parsed JSON value. Only the values that appear in exported chains are supported.
+#[derive(PartialEq, Debug)]
+enum Value {
+    Null,
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Gets the value as a count, if it is a non-negative integer.
+    fn count(&self) -> Option<usize> {
+        match *self {
+            Value::Number(ref number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// A recursive descent parser over the characters of a document.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    /// Parses the value starting at the current position.
+    fn value(&mut self) -> io::Result<Value> {
+        self.whitespace();
+        match self.peek() {
+            Some('n') => {
+                for expected in "null".chars() {
+                    if self.next() != Some(expected) { return Err(invalid("Expected null.")) }
+                }
+                Ok(Value::Null)
+            },
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                if self.close(']') { return Ok(Value::Array(values)) }
+                loop {
+                    values.push(self.value()?);
+                    if self.close(']') { return Ok(Value::Array(values)) }
+                    self.expect(',')?;
+                }
+            },
+            Some('{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if self.close('}') { return Ok(Value::Object(fields)) }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    if self.close('}') { return Ok(Value::Object(fields)) }
+                    self.expect(',')?;
+                }
+            },
+            Some(c) if c.is_ascii_digit() || c == '-' => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+                    self.pos += 1;
+                }
+                Ok(Value::Number(self.chars[start..self.pos].iter().collect()))
+            },
+            _ => Err(invalid("Expected a value.")),
+        }
+    }
+
+    /// Parses a string literal starting at the current position.
+    fn string(&mut self) -> io::Result<String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(match self.next() {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('u') => {
+                        let high = self.hex()?;
+                        let code = if (0xd800..0xdc00).contains(&high) {
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            match self.hex()? {
+                                low @ 0xdc00..=0xdfff =>
+                                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00),
+                                _ => return Err(invalid("Found an unpaired surrogate.")),
+                            }
+                        } else {
+                            high
+                        };
+                        char::from_u32(code).ok_or_else(|| invalid("Found an invalid character."))?
+                    },
+                    Some(c @ '"') | Some(c @ '\\') | Some(c @ '/') => c,
+                    _ => return Err(invalid("Found an unknown escape sequence.")),
+                }),
+                Some(c) => string.push(c),
+                None => return Err(invalid("Found an unterminated string.")),
+            }
+        }
+    }
+
+    /// Parses the four hexadecimal digits of a `\u` escape sequence.
+    fn hex(&mut self) -> io::Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.next().and_then(|c| c.to_digit(16))
+                            .ok_or_else(|| invalid("Expected a hexadecimal digit."))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    /// Consumes the given closing bracket, if it follows.
+    fn close(&mut self, bracket: char) -> bool {
+        self.whitespace();
+        let found = self.peek() == Some(bracket);
+        if found { self.pos += 1; }
+        found
+    }
+
+    /// Consumes the given character, failing if anything else follows.
+    fn expect(&mut self, expected: char) -> io::Result<()> {
+        self.whitespace();
+        if self.next() == Some(expected) {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, format!("Expected {}.", expected)))
+        }
+    }
+
+    /// Skips any whitespace.
+    fn whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let next = self.peek();
+        self.pos += 1;
+        next
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Parser, Value, string_field};
+
+    fn parse(text: &str) -> Value {
+        Parser { chars: text.chars().collect(), pos: 0 }.value().unwrap()
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse(" [null, \"a\\\"\\u00e9\\ud83d\\ude00\", 12] "),
+                   Value::Array(vec![Value::Null, Value::String("a\"é😀".to_owned()),
+                                     Value::Number("12".to_owned())]));
+        assert_eq!(parse("{\"a\": {}, \"b\": []}"),
+                   Value::Object(vec![("a".to_owned(), Value::Object(Vec::new())),
+                                      ("b".to_owned(), Value::Array(Vec::new()))]));
+        assert!(Parser { chars: "[1,".chars().collect(), pos: 0 }.value().is_err());
+    }
+
+    #[test]
+    fn string_fields() {
+        let line = "{\"user\": \"cat\", \"text\": \"I like cats\", \"edited\": null, \"id\": 1}";
+        assert_eq!(string_field(line, "text").unwrap(), Some("I like cats".to_owned()));
+        assert_eq!(string_field(line, "edited").unwrap(), None);
+        assert_eq!(string_field(line, "missing").unwrap(), None);
+        assert!(string_field(line, "id").is_err());
+        assert!(string_field("[\"text\"]", "text").is_err());
+    }
+}
@@ -22,345 +22,3554 @@
 
 extern crate rand;
 
-use std::borrow::ToOwned;
-use std::collections::HashMap;
+use std::borrow::{Borrow, ToOwned};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::collections::hash_map::Entry::{Occupied, Vacant};
-use std::fs::File;
-use std::hash::Hash;
-use std::io::BufReader;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::io::{self, BufReader};
 use std::io::prelude::*;
-use std::iter::Map;
-use std::path::Path;
+use std::iter::{self, FromIterator, FusedIterator, Map};
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
 use rand::{Rng, thread_rng};
 
 mod arc;
-pub use arc::ArcChain;
+pub use arc::{ArcChain, ChainFeeder, ConcurrentChain, SharedChain};
+#[cfg(feature = "registry")] pub use arc::ModelRegistry;
+mod backoff;
+pub use backoff::BackoffChain;
+mod csv;
+mod ensemble;
+pub use ensemble::EnsembleChain;
+mod format;
+mod json;
+mod log;
+pub use log::TrainingLog;
+
+pub mod convert;
+pub mod dot;
+pub mod drift;
+pub mod eval;
+#[cfg(feature = "ffi")] pub mod ffi;
+#[cfg(feature = "markup")] pub mod markup;
+pub mod sampler;
+pub mod tokenizer;
+#[cfg(feature = "test_util")] pub mod test_util;
+use dot::DotOptions;
+use drift::Profile;
+use sampler::{Sampler, WeightedSampler};
+use tokenizer::{Detokenizer, Tokenizer, WhitespaceDetokenizer, WhitespaceTokenizer};
 
 
 /// The definition of all types that can be used in a Chain.
 pub trait Chainable: Eq + Hash {}
 impl<T> Chainable for T where T: Eq + Hash {}
 
-type Token<T> = Option<Rc<T>>;
+/// A reference-counted pointer through which a chain shares its tokens. This is implemented by
+/// `Rc`, for `Chain`, and by `Arc`, for `ArcChain`.
+pub trait SharedPtr<T>: Clone + Deref<Target = T> + Borrow<T> + Eq + Hash {
+    /// Moves the token into a new shared allocation.
+    fn new(token: T) -> Self;
+}
+
+impl<T> SharedPtr<T> for Rc<T> where T: Chainable {
+    fn new(token: T) -> Rc<T> {
+        Rc::new(token)
+    }
+}
+
+impl<T> SharedPtr<T> for Arc<T> where T: Chainable {
+    fn new(token: T) -> Arc<T> {
+        Arc::new(token)
+    }
+}
+
+/// The operations shared by `Chain` and `ArcChain`, for code that works with either. Each method
+/// behaves like the chain's own method of the same name.
+pub trait MarkovChain<T> where T: Chainable {
+    /// The pointer through which generated tokens are shared, i.e. `Rc<T>` or `Arc<T>`.
+    type Token: Deref<Target = T> + Clone;
+    /// The infinite iterator of generated collections returned by `iter`.
+    type Iter<'a>: Iterator<Item = Vec<Self::Token>> where Self: 'a;
+    /// The finite iterator of generated collections returned by `iter_for`.
+    type SizedIter<'a>: Iterator<Item = Vec<Self::Token>> where Self: 'a;
+
+    /// Determines whether or not the chain is empty.
+    fn is_empty(&self) -> bool;
+    /// Gets the order of the chain.
+    fn get_order(&self) -> usize;
+    /// Feeds the chain a collection of tokens.
+    fn feed<S: IntoIterator<Item = T>>(&mut self, tokens: S) -> &mut Self;
+    /// Generates a collection of tokens from the chain.
+    fn generate(&self) -> Vec<Self::Token>;
+    /// Generates a collection of tokens from the chain, starting with the given token.
+    fn generate_from_token(&self, token: T) -> Vec<Self::Token>;
+    /// Produces an infinite iterator of generated token collections.
+    fn iter(&self) -> Self::Iter<'_>;
+    /// Produces an iterator for the specified number of generated token collections.
+    fn iter_for(&self, size: usize) -> Self::SizedIter<'_>;
+
+    /// Feeds a string of text into the chain, splitting it on whitespace.
+    fn feed_str(&mut self, string: &str) -> &mut Self where Self: MarkovChain<String> {
+        MarkovChain::<String>::feed(self, WhitespaceTokenizer.tokenize(string))
+    }
+
+    /// Generates a random string of text.
+    fn generate_str(&self) -> String where Self: MarkovChain<String> {
+        let tokens = MarkovChain::<String>::generate(self);
+        let tokens: Vec<_> = tokens.iter().map(|s| &s[..]).collect();
+        WhitespaceDetokenizer.detokenize(&tokens)
+    }
+
+    /// Generates a random string of text starting with the desired token. This returns an empty
+    /// string if the token is not found.
+    fn generate_str_from_token(&self, string: &str) -> String where Self: MarkovChain<String> {
+        let tokens = MarkovChain::<String>::generate_from_token(self, string.to_owned());
+        let tokens: Vec<_> = tokens.iter().map(|s| &s[..]).collect();
+        WhitespaceDetokenizer.detokenize(&tokens)
+    }
+}
+
+type Token<P> = Option<P>;
+
+/// Counts the continuations of each state.
+type Transitions<P, H = RandomState> = HashMap<Vec<Token<P>>, HashMap<Token<P>, usize, H>, H>;
+
+/// Maps each token to the states ending in it, along with how often each state was observed.
+type SuffixIndex<P, H> = HashMap<P, Vec<(Vec<Token<P>>, u64)>, H>;
+
+/// Maps each state to the states leading into it, along with how often each transition was
+/// observed.
+type PredecessorIndex<P, H> = HashMap<Vec<Token<P>>, HashMap<Vec<Token<P>>, usize, H>, H>;
+
+/// Indexes over the states of a chain, each built on first use and discarded on feeding.
+#[derive(Debug)]
+struct Indexes<P, H> {
+    suffixes: Option<SuffixIndex<P, H>>,
+    predecessors: Option<PredecessorIndex<P, H>>,
+}
+
+impl<P, H> Default for Indexes<P, H> {
+    fn default() -> Indexes<P, H> {
+        Indexes { suffixes: None, predecessors: None }
+    }
+}
+
+/// What generation does upon reaching a state with no recorded continuations.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DeadEndPolicy {
+    /// End the generated sequence at the dead end.
+    Terminate,
+    /// Continue from a random state, chosen in proportion to how often each state was observed.
+    Restart,
+}
+
+/// The reasons a seed cannot be used to continue generation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SeedError {
+    /// The seed does not have exactly as many tokens as the order of the chain.
+    WrongLength {
+        /// The order of the chain.
+        expected: usize,
+        /// The number of tokens in the seed.
+        found: usize,
+    },
+    /// The seed does not correspond to any state observed while feeding the chain.
+    Unknown,
+}
+
+impl fmt::Display for SeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SeedError::WrongLength { expected, found } =>
+                write!(f, "Expected a seed of {} tokens to match the chain's order, found {}.",
+                       expected, found),
+            SeedError::Unknown => write!(f, "The seed does not appear in the chain."),
+        }
+    }
+}
+
+impl Error for SeedError {}
+
+/// The ways in which a chain can break the invariants upheld by feeding, as found by `validate`,
+/// e.g. in a chain loaded from a corrupted file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ValidationError {
+    /// A state does not have exactly as many tokens as the order of the chain.
+    WrongStateLength {
+        /// The order of the chain.
+        expected: usize,
+        /// The number of tokens in the state.
+        found: usize,
+    },
+    /// A transition was recorded with a count of zero.
+    ZeroCount,
+    /// The state at the start of a collection, from which generation begins, is missing.
+    MissingStartState,
+    /// A transition leads to a token that is not part of any state, so generation would reach a
+    /// state with no recorded continuations, not even the end of a collection.
+    DanglingTransition,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::WrongStateLength { expected, found } =>
+                write!(f, "Expected every state to have {} tokens to match the chain's order, \
+                           found one with {}.", expected, found),
+            ValidationError::ZeroCount => write!(f, "Found a transition with a count of zero."),
+            ValidationError::MissingStartState =>
+                write!(f, "The state at the start of a collection is missing."),
+            ValidationError::DanglingTransition =>
+                write!(f, "Found a transition to a token that is not part of any state."),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// The reasons a chain cannot generate a collection, as returned by `try_generate` and
+/// `try_generate_from_token`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GenerateError {
+    /// The chain has not been fed anything it can generate from.
+    Empty,
+    /// The token does not appear in any state observed while feeding the chain.
+    UnknownToken,
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GenerateError::Empty => write!(f, "The chain is empty."),
+            GenerateError::UnknownToken => write!(f, "The token does not appear in the chain."),
+        }
+    }
+}
+
+impl Error for GenerateError {}
+
+/// A summary of the progress made while feeding text into a chain of strings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct FeedProgress {
+    /// The number of bytes read, including line endings.
+    pub bytes: usize,
+    /// The number of lines read.
+    pub lines: usize,
+    /// The number of tokens fed into the chain.
+    pub tokens: usize,
+}
+
+/// Options controlling the search performed by `beam_search`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BeamOptions {
+    /// The number of partial sequences kept after each step, and of results returned.
+    pub width: usize,
+    /// The maximum number of tokens in a sequence. Longer sequences are abandoned.
+    pub max_len: usize,
+    /// The exponent applied to a sequence's length before dividing its log-probability by it.
+    /// Zero ranks sequences by raw probability, which favors the shortest ones, while one ranks
+    /// them by average log-probability per token.
+    pub length_penalty: f64,
+}
+
+impl Default for BeamOptions {
+    fn default() -> BeamOptions {
+        BeamOptions { width: 5, max_len: 50, length_penalty: 1.0 }
+    }
+}
+
+/// A summary of the size and shape of a chain, as returned by `stats`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChainStats {
+    /// The number of states with at least one recorded continuation.
+    pub states: usize,
+    /// The number of distinct transitions from a state to a continuation.
+    pub transitions: usize,
+    /// The number of distinct tokens fed into the chain.
+    pub vocabulary: usize,
+    /// The total number of tokens fed into the chain.
+    pub tokens: usize,
+    /// The average number of distinct continuations of a state, or zero if there are no states.
+    pub branching: f64,
+    /// The approximate number of bytes used by the chain, excluding the tokens themselves.
+    pub memory: usize,
+}
+
+/// A transition learned by a chain, as the state, the token that followed it, and the number of
+/// times it was observed.
+pub type Edge<'a, T> = (Vec<Option<&'a T>>, Option<&'a T>, usize);
+
+/// A table of the number of times each token followed each state, as taken by `from_counts` and
+/// returned by `to_counts`. `None` stands for the padding at the start of a collection in a state,
+/// and for the end of a collection as a following token.
+pub type Counts<T> = HashMap<Vec<Option<T>>, HashMap<Option<T>, usize>>;
+
+/// The states and tokens of a chain that may indicate problems with the model, as returned by
+/// `analyze`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Analysis<'a, T: 'a> {
+    /// The states from which generation can only end, either because the end of a collection is
+    /// their only continuation or because they have no continuations at all. These cause abrupt
+    /// endings. States are given as in `edges`, in arbitrary order.
+    pub terminal_states: Vec<Vec<Option<&'a T>>>,
+    /// The fed tokens that can never be generated from the start state, which only waste memory.
+    /// These are in the order in which each was first fed.
+    pub unreachable: Vec<&'a T>,
+}
+
+impl BeamOptions {
+    /// Scores a sequence with the given log-probability and length, including its end.
+    fn score(&self, log_prob: f64, len: usize) -> f64 {
+        log_prob / (len.max(1) as f64).powf(self.length_penalty)
+    }
+}
+
+/// A generic [Markov chain](https://en.wikipedia.org/wiki/Markov_chain) for almost any type. This
+/// uses HashMaps internally, and so Eq and Hash are both required.
+pub type Chain<T, H = RandomState> = GenericChain<T, Rc<T>, H>;
+
+/// A Markov chain whose tokens are shared through the pointer `P`. This is used through its
+/// aliases, `Chain` and `ArcChain`, which only differ in whether the chain can be sent between
+/// threads.
+///
+/// States are hashed with `H`, which defaults to the standard library's SipHash. As states are
+/// vectors of tokens, hashing dominates both feeding and generation, so a faster non-cryptographic
+/// hasher can be substituted when the fed data is trusted, by constructing the chain with
+/// `default` rather than `new`.
+///
+/// Transitions are counted with `usize`, and counts saturate at `usize::MAX` rather than
+/// overflowing, so even long-running chains on 32-bit targets never panic or wrap around. Totals
+/// used when sampling are computed in 64 bits.
+#[derive(Debug)]
+pub struct GenericChain<T, P, H = RandomState> where T: Chainable, P: SharedPtr<T> {
+    map: Transitions<P, H>,
+    order: usize,
+    dead_end: DeadEndPolicy,
+    bidirectional: bool,
+    smoothing: f64,
+    max_count: usize,
+    starts: Vec<(Vec<Token<P>>, usize)>,
+    lengths: BTreeMap<usize, usize>,
+    tags: HashMap<String, Transitions<P, H>>,
+    /// Every fed token, in the order in which each was first fed.
+    vocabulary: Vec<P>,
+    known: HashSet<P, H>,
+    ngram_len: usize,
+    ngrams: HashSet<Vec<P>, H>,
+    /// Fingerprints of every collection fed, if duplicate collections are being skipped.
+    seen: Option<HashSet<u64>>,
+    indexes: RwLock<Indexes<P, H>>,
+    marker: PhantomData<T>,
+}
+
+impl<T, P, H> PartialEq for GenericChain<T, P, H>
+    where T: Chainable, P: SharedPtr<T>, H: BuildHasher {
+    fn eq(&self, other: &GenericChain<T, P, H>) -> bool {
+        self.map == other.map && self.order == other.order && self.dead_end == other.dead_end &&
+            self.bidirectional == other.bidirectional && self.smoothing == other.smoothing &&
+            self.max_count == other.max_count && self.starts == other.starts &&
+            self.lengths == other.lengths && self.tags == other.tags
+    }
+}
+
+impl<T, P, H> Clone for GenericChain<T, P, H>
+    where T: Chainable, P: SharedPtr<T>, H: BuildHasher + Clone {
+    fn clone(&self) -> GenericChain<T, P, H> {
+        GenericChain {
+            map: self.map.clone(),
+            order: self.order,
+            dead_end: self.dead_end,
+            bidirectional: self.bidirectional,
+            smoothing: self.smoothing,
+            max_count: self.max_count,
+            starts: self.starts.clone(),
+            lengths: self.lengths.clone(),
+            tags: self.tags.clone(),
+            vocabulary: self.vocabulary.clone(),
+            known: self.known.clone(),
+            ngram_len: self.ngram_len,
+            ngrams: self.ngrams.clone(),
+            seen: self.seen.clone(),
+            indexes: RwLock::new(Indexes::default()),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, P> GenericChain<T, P> where T: Chainable, P: SharedPtr<T> {
+    /// Constructs a new Markov chain.
+    pub fn new() -> GenericChain<T, P> {
+        GenericChain::default()
+    }
+}
+
+impl<T, P, H> GenericChain<T, P, H> where T: Chainable, P: SharedPtr<T>, H: BuildHasher + Default {
+    /// Choose a specific Markov chain order. The order is the number of previous tokens to use
+    /// as the index into the map. An order of zero produces a unigram model, in which every token
+    /// (including the end of a sequence) is drawn independently from the frequency distribution of
+    /// all fed tokens.
+    pub fn order(&mut self, order: usize) -> &mut GenericChain<T, P, H> {
+        let start = vec!(None; self.order);
+        if self.map.get(&start).is_some_and(|states| states.is_empty()) {
+            self.map.remove(&start);
+        }
+        self.order = order;
+        self.map.insert(vec!(None; self.order), HashMap::default());
+        self.starts.clear();
+        *self.indexes.get_mut().unwrap() = Indexes::default();
+        self
+    }
+
+    /// Choose what generation does upon reaching a state with no recorded continuations. Such
+    /// states cannot arise from feeding alone, but may appear in models that were edited or only
+    /// partially loaded. The default is `DeadEndPolicy::Terminate`.
+    pub fn dead_end_policy(&mut self, policy: DeadEndPolicy) -> &mut GenericChain<T, P, H> {
+        self.dead_end = policy;
+        self
+    }
+
+    /// Choose whether feeding also records which states lead into each state. Backward
+    /// generation, such as `generate_to_token` and `generate_backwards_from_token`, relies on this
+    /// record. Without this mode, the record is discarded whenever the chain is fed and rebuilt
+    /// from scratch on the next backward generation, which is wasteful when feeding and backward
+    /// generation are interleaved. The default is `false`.
+    pub fn bidirectional(&mut self, bidirectional: bool) -> &mut GenericChain<T, P, H> {
+        self.bidirectional = bidirectional;
+        self
+    }
+
+    /// Registers a state from which `generate` may begin, with the given weight. Once any start
+    /// states are registered, `generate` begins each collection with the tokens of one of them,
+    /// chosen in proportion to the weights, instead of from the start of a collection. The state
+    /// must be a valid seed, as checked by `validate_seed`. Changing the order of the chain removes
+    /// every start state, and a weight of zero registers nothing.
+    pub fn add_start_state(&mut self, state: &[T], weight: usize) -> Result<(), SeedError>
+        where T: Clone {
+        self.validate_seed(state)?;
+        if weight > 0 {
+            let state = state.iter().map(|token| Some(P::new(token.clone()))).collect();
+            self.starts.push((state, weight));
+        }
+        Ok(())
+    }
+
+    /// Removes every registered start state, so that `generate` begins from the start of a
+    /// collection again.
+    pub fn clear_start_states(&mut self) -> &mut GenericChain<T, P, H> {
+        self.starts.clear();
+        self
+    }
+
+    /// Choose the length of the n-grams of fed collections to record for `novelty`, or zero to
+    /// stop recording them. Only collections fed afterwards are recorded, and changing the length
+    /// discards the n-grams recorded so far. Recording every n-gram takes memory comparable to the
+    /// fed collections themselves. The default is zero.
+    pub fn track_ngrams(&mut self, len: usize) -> &mut GenericChain<T, P, H> {
+        if len != self.ngram_len { self.ngrams.clear(); }
+        self.ngram_len = len;
+        self
+    }
+
+    /// Choose whether to skip collections identical to one fed before, so that repeated text such
+    /// as copypasta does not dominate the chain. Only collections fed afterwards are remembered,
+    /// and disabling this forgets them. Each collection is remembered by a 64-bit fingerprint, so
+    /// the memory used is small and independent of its length. The default is false.
+    pub fn deduplicate(&mut self, deduplicate: bool) -> &mut GenericChain<T, P, H> {
+        if deduplicate != self.seen.is_some() {
+            self.seen = if deduplicate { Some(HashSet::new()) } else { None };
+        }
+        self
+    }
+
+    /// Choose the largest number of times any single transition may be counted, limiting the
+    /// influence of extremely repetitive text while preserving the rest of the distribution.
+    /// Counts already above the limit are clamped to it, and further observations of a transition
+    /// at the limit are ignored. The default is `usize::MAX`, i.e. no limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_count(&mut self, max: usize) -> &mut GenericChain<T, P, H> {
+        assert!(max > 0, "The maximum count must be positive.");
+        self.max_count = max;
+        for states in self.map.values_mut().chain(self.tags.values_mut().flat_map(|map| {
+            map.values_mut()
+        })) {
+            for count in states.values_mut() {
+                *count = (*count).min(max);
+            }
+        }
+        self
+    }
+
+    /// Choose a pseudo-count to add to every possible continuation of every state, i.e. every fed
+    /// token and the end of the collection. With smoothing, generation occasionally takes
+    /// transitions never observed while feeding, and `score` and `perplexity` treat such
+    /// transitions as unlikely rather than impossible. The default is zero, i.e. no smoothing.
+    pub fn smoothing(&mut self, smoothing: f64) -> &mut GenericChain<T, P, H> {
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Gets the order of the chain, i.e. the number of previous tokens used as the index into the
+    /// map.
+    pub fn get_order(&self) -> usize {
+        self.order
+    }
+
+    /// Checks that a seed can be used to continue generation, i.e. that it consists of exactly
+    /// `order` tokens and that those tokens form a state that was observed while feeding. A valid
+    /// seed can be passed to `resume`.
+    pub fn validate_seed(&self, seed: &[T]) -> Result<(), SeedError> where T: Clone {
+        if seed.len() != self.order {
+            return Err(SeedError::WrongLength { expected: self.order, found: seed.len() })
+        }
+        let state: Vec<_> = seed.iter().map(|token| Some(P::new(token.clone()))).collect();
+        if self.map.contains_key(&state) { Ok(()) } else { Err(SeedError::Unknown) }
+    }
+
+    /// Gets the observed continuations of the state formed by the given tokens, along with the
+    /// probability of each, without smoothing. The end of the collection is given as `None`. Only
+    /// the last `order` tokens form the state, and fewer tokens form a state at the start of a
+    /// collection, so `transitions(&[])` gives the distribution of first tokens. This yields
+    /// nothing if the state was never observed.
+    pub fn transitions(&self, state: &[T]) -> impl Iterator<Item = (Option<&T>, f64)> + '_
+        where T: Clone {
+        let states = self.map.get(&self.state_of(state));
+        let total = states.map_or(0, |states| sum_counts(states.values())) as f64;
+        states.into_iter().flatten().filter(|&(_, &count)| count > 0)
+              .map(move |(token, &count)| (token.as_deref(), count as f64 / total))
+    }
+
+    /// Gets the probability of the given continuation of the state formed by the given tokens,
+    /// as in `transitions`. The end of the collection is given as `None`.
+    pub fn probability(&self, state: &[T], next: Option<&T>) -> f64 where T: Clone {
+        self.transitions(state).find(|&(token, _)| token == next).map_or(0.0, |(_, p)| p)
+    }
+
+    /// Suggests up to `n` of the tokens most likely to follow the given tokens, most likely first,
+    /// along with their probabilities. The state is formed as in `transitions`. If it was never
+    /// observed, this backs off to the combined continuations of every state ending in the longest
+    /// observed suffix of the tokens, and ultimately to the frequencies of all fed tokens. The end
+    /// of a collection is never suggested, so the probabilities may sum to less than one.
+    pub fn suggest(&self, context: &[T], n: usize) -> Vec<(P, f64)> where T: Clone {
+        let suffix: Vec<_> = context.iter().map(|token| Some(P::new(token.clone()))).collect();
+        let exact: Vec<_> = self.map.get(&self.state_of(context)).into_iter().collect();
+        let backoff = (0..=self.order.min(suffix.len())).rev().map(|len| {
+            self.map.iter().filter(|&(state, _)| state.ends_with(&suffix[suffix.len() - len..]))
+                .map(|(_, states)| states).collect()
+        });
+        let matching = iter::once(exact).chain(backoff).find(|matching: &Vec<_>| {
+            matching.iter().any(|states| states.values().any(|&count| count > 0))
+        }).unwrap_or_default();
+        let mut counts: HashMap<&P, usize> = HashMap::new();
+        let mut total = 0;
+        for (token, &count) in matching.into_iter().flatten() {
+            total = usize::saturating_add(total, count);
+            if let Some(token) = token.as_ref() {
+                let sum = counts.entry(token).or_insert(0);
+                *sum = sum.saturating_add(count);
+            }
+        }
+        let mut suggestions: Vec<_> = counts.into_iter().filter(|&(_, count)| count > 0)
+            .map(|(token, count)| (token.clone(), count as f64 / total as f64)).collect();
+        suggestions.sort_by(|a, b| b.1.total_cmp(&a.1));
+        suggestions.truncate(n);
+        suggestions
+    }
+
+    /// Forms the state reached after the given tokens, as described in `transitions`.
+    fn state_of(&self, tokens: &[T]) -> Vec<Token<P>> where T: Clone {
+        let start = tokens.len().saturating_sub(self.order);
+        let mut state = vec!(None; self.order - (tokens.len() - start));
+        state.extend(tokens[start..].iter().map(|token| Some(P::new(token.clone()))));
+        state
+    }
+
+    /// Computes the natural log-probability of the chain generating exactly the given tokens, with
+    /// the chain's smoothing. Without smoothing, this is negative infinity if the tokens include a
+    /// transition never observed while feeding.
+    pub fn score(&self, tokens: &[T]) -> f64 where T: Clone {
+        self.score_smoothed(tokens, self.smoothing)
+    }
+
+    /// Computes the natural log-probability of the chain generating exactly the given tokens, with
+    /// additive smoothing. The smoothing is a pseudo-count added to every possible continuation of
+    /// every state, i.e. every fed token and the end of the collection, so that transitions never
+    /// observed while feeding are unlikely rather than impossible.
+    pub fn score_smoothed(&self, tokens: &[T], smoothing: f64) -> f64 where T: Clone {
+        let vocabulary = (self.known.len() + 1) as f64;
+        let mut log_prob = 0.0;
+        let mut curs = vec!(None; self.order);
+        let tokens = tokens.iter().map(|token| Some(P::new(token.clone()))).chain(Some(None));
+        for token in tokens {
+            let (count, total) = match self.map.get(&curs) {
+                Some(states) => (states.get(&token).cloned().unwrap_or(0),
+                                 sum_counts(states.values())),
+                None => (0, 0),
+            };
+            let total = total as f64 + smoothing * vocabulary;
+            if total == 0.0 { return f64::NEG_INFINITY }
+            log_prob += ((count as f64 + smoothing) / total).ln();
+            self.advance(&mut curs, token);
+        }
+        log_prob
+    }
+
+    /// Computes the perplexity of the chain on the given collections of tokens, e.g. held-out
+    /// validation data. This is the exponential of the average negative log-probability per
+    /// token, counting the end of each collection as a token. Lower is better, and a chain that
+    /// always generates exactly the given collections scores one. Collections are scored with
+    /// `score`, so without smoothing this is infinite if any of them includes a transition never
+    /// observed while feeding. This is NaN if no collections are given.
+    pub fn perplexity<I>(&self, sequences: I) -> f64
+        where I: IntoIterator<Item = Vec<T>>, T: Clone {
+        self.perplexity_smoothed(sequences, self.smoothing)
+    }
+
+    /// Computes the perplexity of the chain on the given collections of tokens like `perplexity`,
+    /// scoring each collection with `score_smoothed` and the given smoothing.
+    pub fn perplexity_smoothed<I>(&self, sequences: I, smoothing: f64) -> f64
+        where I: IntoIterator<Item = Vec<T>>, T: Clone {
+        let (mut log_prob, mut tokens) = (0.0, 0);
+        for sequence in sequences {
+            log_prob += self.score_smoothed(&sequence, smoothing);
+            tokens += sequence.len() + 1;
+        }
+        (-log_prob / tokens as f64).exp()
+    }
+
+    /// Measures how much of the given output was not copied verbatim from the fed collections, as
+    /// the fraction of its n-grams that never appeared in a collection fed while `track_ngrams`
+    /// was enabled. Output shorter than the n-gram length is entirely novel. This panics if n-gram
+    /// tracking is not enabled.
+    pub fn novelty(&self, output: &[T]) -> f64 where T: Clone {
+        assert!(self.ngram_len > 0, "N-gram tracking is not enabled.");
+        if output.len() < self.ngram_len { return 1.0 }
+        let windows = output.windows(self.ngram_len);
+        let total = windows.len();
+        let novel = windows.filter(|window| {
+            let ngram: Vec<_> = window.iter().map(|token| P::new(token.clone())).collect();
+            !self.ngrams.contains(&ngram)
+        }).count();
+        novel as f64 / total as f64
+    }
+
+    /// Computes the Shannon entropy, in bits, of the continuations of the state formed by the
+    /// given tokens as in `transitions`. This is zero for a state that always has the same
+    /// continuation, and for a state that was never observed.
+    pub fn state_entropy(&self, state: &[T]) -> f64 where T: Clone {
+        self.transitions(state).map(|(_, p)| -p * p.log2()).sum()
+    }
+
+    /// Computes the average entropy, in bits, of the continuations of every state, weighted by
+    /// how often each state was observed. Low entropy means that generation mostly reproduces the
+    /// fed collections verbatim, and zero means it always does.
+    pub fn entropy(&self) -> f64 {
+        let (mut sum, mut weight) = (0.0, 0.0);
+        for states in self.map.values() {
+            let total = sum_counts(states.values()) as f64;
+            for &count in states.values().filter(|&&count| count > 0) {
+                let p = count as f64 / total;
+                sum -= total * p * p.log2();
+            }
+            weight += total;
+        }
+        if weight == 0.0 { 0.0 } else { sum / weight }
+    }
+
+    /// Measures how differently the chain and another continue the states they have in common,
+    /// e.g. to compare chains fed by different authors. This is the Jensen-Shannon divergence
+    /// between their distributions of continuations, in bits, averaged over the shared states in
+    /// proportion to how often each was observed by either chain. It ranges from zero, for chains
+    /// that continue every shared state alike, to one, which is also the result if the chains
+    /// share no states, e.g. because they differ in order.
+    pub fn divergence(&self, other: &GenericChain<T, P, H>) -> f64 {
+        let (mut sum, mut weight) = (0.0, 0.0);
+        for (state, states) in &self.map {
+            let others = match other.map.get(state) {
+                Some(others) => others,
+                None => continue,
+            };
+            let total = sum_counts(states.values()) as f64;
+            let other_total = sum_counts(others.values()) as f64;
+            if total == 0.0 || other_total == 0.0 { continue }
+            let mut divergence = 0.0;
+            let only_others = others.keys().filter(|token| !states.contains_key(*token));
+            for token in states.keys().chain(only_others) {
+                let p = states.get(token).cloned().unwrap_or(0) as f64 / total;
+                let q = others.get(token).cloned().unwrap_or(0) as f64 / other_total;
+                let m = (p + q) / 2.0;
+                if p > 0.0 { divergence += p * (p / m).log2() / 2.0 }
+                if q > 0.0 { divergence += q * (q / m).log2() / 2.0 }
+            }
+            sum += divergence * (total + other_total);
+            weight += total + other_total;
+        }
+        if weight == 0.0 { 1.0 } else { sum / weight }
+    }
+
+    /// Summarizes the tokens fed into the chain, keeping the given number of most frequent
+    /// tokens. Profiles taken at different times can be compared with a `DriftMonitor`.
+    pub fn profile(&self, top: usize) -> Profile<T> where T: Clone {
+        let mut counts: HashMap<&T, usize> = HashMap::new();
+        for states in self.map.values() {
+            for (token, &count) in states {
+                if let Some(ref token) = *token {
+                    let total = counts.entry(&**token).or_insert(0);
+                    *total = total.saturating_add(count);
+                }
+            }
+        }
+        Profile::from_counts(counts, top)
+    }
+
+    /// Summarizes the size and shape of the chain, e.g. for capacity planning.
+    pub fn stats(&self) -> ChainStats {
+        let states = self.len();
+        let transitions = self.map.values().map(|states| states.len()).sum();
+        let token = size_of::<Token<P>>();
+        let state = size_of::<Vec<Token<P>>>() + self.order * token +
+                    size_of::<HashMap<Token<P>, usize>>();
+        let memory = self.map.len() * state + transitions * (token + size_of::<usize>()) +
+                     self.vocabulary.len() * 2 * size_of::<P>();
+        ChainStats {
+            states,
+            transitions,
+            vocabulary: self.known.len(),
+            tokens: self.token_count(),
+            branching: if states == 0 { 0.0 } else { transitions as f64 / states as f64 },
+            memory,
+        }
+    }
+
+    /// Finds the states from which generation can only end, and the fed tokens that generation
+    /// can never reach from the start state. Neither arises from feeding alone at a fixed order,
+    /// except for states whose only continuation is the end of a collection.
+    pub fn analyze(&self) -> Analysis<'_, T> {
+        if self.is_empty() {
+            return Analysis { terminal_states: Vec::new(), unreachable: Vec::new() }
+        }
+        let terminal_states = self.map.iter().filter(|&(state, states)| {
+            state.len() == self.order &&
+                states.iter().all(|(token, &count)| token.is_none() || count == 0)
+        }).map(|(state, _)| state.iter().map(|token| token.as_deref()).collect()).collect();
+        let mut reached = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut queue: Vec<_> = self.map.get_key_value(&vec!(None; self.order))
+                                        .map(|(state, _)| state).into_iter().collect();
+        while let Some(state) = queue.pop() {
+            if !visited.insert(state) { continue }
+            let tokens = self.map[state].iter().filter(|&(_, &count)| count > 0)
+                                               .filter_map(|(token, _)| token.as_ref());
+            for token in tokens {
+                reached.insert(&**token);
+                let mut next = state.clone();
+                self.advance(&mut next, Some(token.clone()));
+                if let Some((next, _)) = self.map.get_key_value(&next) { queue.push(next) }
+            }
+        }
+        let unreachable = self.vocabulary.iter().map(|token| &**token)
+                              .filter(|token| !reached.contains(token)).collect();
+        Analysis { terminal_states, unreachable }
+    }
+
+    /// Iterates over every transition learned by the chain, as the state, the token that followed
+    /// it, and the number of times it was observed, in arbitrary order. States at the start of a
+    /// collection are padded with `None`, and the end of a collection is given as a `None` token.
+    pub fn edges(&self) -> impl Iterator<Item = Edge<'_, T>> + '_ {
+        self.map.iter().flat_map(|(state, states)| {
+            states.iter().filter(|&(_, &count)| count > 0).map(move |(token, &count)| {
+                (state.iter().map(|token| token.as_deref()).collect(), token.as_deref(), count)
+            })
+        })
+    }
+
+    /// Gets every transition learned by the chain like `edges`, but sorted by state and then by
+    /// token, so that the result does not depend on the chain's hashing. This is useful for
+    /// reproducible exports and snapshot tests.
+    pub fn sorted_edges(&self) -> Vec<Edge<'_, T>> where T: Ord {
+        let mut edges: Vec<_> = self.edges().collect();
+        edges.sort();
+        edges
+    }
+
+    /// Writes the transitions learned by the chain, as yielded by `edges`, as a Graphviz DOT graph.
+    /// Each state is a node, labelled with its tokens, and each transition is an edge labelled
+    /// with the number of times it was observed. The options limit which transitions are written,
+    /// which keeps graphs of larger chains readable.
+    pub fn to_dot<W: Write>(&self, writer: &mut W, options: DotOptions<T>) -> io::Result<()>
+        where T: fmt::Display {
+        dot::write(self.edges(), options, writer)
+    }
+
+    /// Writes the chain as a DOT graph like `to_dot`, but with nodes and edges in the order given
+    /// by `sorted_edges`, so that equal chains always produce identical output.
+    pub fn to_dot_sorted<W: Write>(&self, writer: &mut W, options: DotOptions<T>) -> io::Result<()>
+        where T: fmt::Display + Ord {
+        dot::write(self.sorted_edges().into_iter(), options, writer)
+    }
+
+    /// Saves the transitions learned by the chain, along with its order, in a line-based text
+    /// format. Tokens are written with `Display` and read back by `load` with `FromStr`, so the
+    /// two must agree. Settings such as smoothing or the dead-end policy, start states, tags and
+    /// the distribution of fed lengths are not saved.
+    ///
+    /// Transitions are written one at a time as they are visited, without building a copy of the
+    /// chain, so saving needs little memory beyond the chain itself even for very large chains.
+    /// As this makes many small writes, the writer should be buffered, e.g. with a `BufWriter`.
+    ///
+    /// The output starts with the version of the format. Chains saved by earlier versions of this
+    /// crate keep loading, and chains saved in a newer format are rejected by `load` with an error
+    /// rather than misread.
+    pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> where T: fmt::Display {
+        format::write(self.order, self.edges(), writer)
+    }
+
+    /// Loads a chain saved by `save`. The input is read and added to the chain one transition at
+    /// a time, so it is never held in memory in full. This fails with an error of kind
+    /// `InvalidData` if the input is malformed or a token cannot be parsed. Input that is well
+    /// formed but could not have been saved from a fed chain is not rejected, so a chain loaded
+    /// from an untrusted source should be checked with `validate`.
+    pub fn load<R: BufRead>(reader: R) -> io::Result<GenericChain<T, P, H>> where T: FromStr {
+        let mut reader = format::Reader::new(reader)?;
+        let mut chain = GenericChain::default();
+        chain.order(reader.order());
+        while let Some((state, token, count)) = reader.read_edge()? {
+            chain.add_count(state, token, count);
+        }
+        Ok(chain)
+    }
+
+    /// Writes the transitions learned by the chain, along with its order, as a JSON document
+    /// meant for reading by people, e.g. to inspect or diff small chains. Each state is given
+    /// with the number of times each token followed it, and states and tokens are sorted by their
+    /// text, so that equal chains produce identical documents. As with `save`, settings are not
+    /// written. Sorting requires a copy of every transition as text, and reading back requires the
+    /// whole document in memory, so large chains should be saved with `save` instead.
+    pub fn to_json<W: Write>(&self, writer: &mut W) -> io::Result<()> where T: fmt::Display {
+        json::write(self.order, self.edges(), writer)
+    }
+
+    /// Reads a chain written by `to_json`. This fails with an error of kind `InvalidData` if the
+    /// document is malformed or a token cannot be parsed.
+    pub fn from_json<R: Read>(reader: R) -> io::Result<GenericChain<T, P, H>> where T: FromStr {
+        let (order, edges) = json::read(reader)?;
+        let mut chain = GenericChain::default();
+        chain.order(order);
+        for (state, token, count) in edges {
+            chain.add_count(state, token, count);
+        }
+        Ok(chain)
+    }
+
+    /// Feeds the collections recorded in a `TrainingLog` at the given path into the chain,
+    /// skipping the first `skip` of them, and returns the number of collections in the log. To
+    /// rebuild a chain, replay the whole log into an empty chain; to catch up a chain loaded from
+    /// a save, pass the number of entries the log held when it was saved. An incomplete entry at
+    /// the end of the log is ignored.
+    pub fn replay_log<Q: AsRef<Path>>(&mut self, path: Q, skip: usize) -> io::Result<usize>
+        where T: FromStr {
+        let reader = BufReader::new(File::open(path)?);
+        log::replay(reader, skip, |tokens| { self.feed(tokens); })
+    }
+
+    /// Writes every transition learned by the chain as a row of a CSV table, for analysis in
+    /// tools such as spreadsheets or data frames. The table has a column for each token of the
+    /// state, named `context_1` onwards, followed by the `next` token and the `count`. The padding
+    /// at the start of a collection, and the end of a collection, are written as empty fields. As
+    /// with `save`, rows are written one at a time, so the writer should be buffered.
+    pub fn to_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> where T: fmt::Display {
+        csv::write(self.order, self.edges(), writer)
+    }
+
+    /// Constructs a chain of the given order from a table of precomputed counts, e.g. an n-gram
+    /// table produced by another tool, instead of feeding it the collections the table was
+    /// computed from. Counts for the same transition are added to each other, and zero counts are
+    /// ignored. As only transitions are given, the distribution of fed lengths is left empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any state does not hold exactly `order` tokens. Use `try_from_counts` to handle
+    /// such tables without panicking.
+    pub fn from_counts(counts: Counts<T>, order: usize) -> GenericChain<T, P, H> {
+        GenericChain::try_from_counts(counts, order).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Constructs a chain of the given order from a table of precomputed counts, as with
+    /// `from_counts`, returning an error instead of panicking if any state does not hold exactly
+    /// `order` tokens.
+    pub fn try_from_counts(counts: Counts<T>, order: usize)
+        -> Result<GenericChain<T, P, H>, ValidationError> {
+        let mut chain = GenericChain::default();
+        chain.order(order);
+        for (state, tokens) in counts {
+            if state.len() != order {
+                let found = state.len();
+                return Err(ValidationError::WrongStateLength { expected: order, found })
+            }
+            let state: Vec<_> = state.into_iter()
+                                     .map(|token| token.map(|token| chain.intern(token)))
+                                     .collect();
+            for (token, count) in tokens.into_iter().filter(|&(_, count)| count > 0) {
+                let token = token.map(|token| chain.intern(token));
+                let total = chain.map.entry(state.clone()).or_default().entry(token).or_insert(0);
+                *total = total.saturating_add(count);
+            }
+        }
+        Ok(chain)
+    }
+
+    /// Gets the number of times each token followed each state, in the form taken by
+    /// `from_counts`, so that the chain can be processed by other tools.
+    pub fn to_counts(&self) -> Counts<T> where T: Clone {
+        let mut counts = Counts::new();
+        for (state, token, count) in self.edges() {
+            let state = state.into_iter().map(|token| token.cloned()).collect();
+            counts.entry(state).or_insert_with(HashMap::new).insert(token.cloned(), count);
+        }
+        counts
+    }
+
+    /// Checks that the chain upholds the invariants maintained by feeding it, which should be
+    /// done before trusting a chain loaded from a file or received over a network:
+    ///
+    /// - the state at the start of a collection exists,
+    /// - every state, including those recorded under tags, holds exactly `order` tokens,
+    /// - every recorded transition was observed at least once, and
+    /// - every transition to a token leads to a recorded state, so that generation never reaches
+    ///   a state without continuations.
+    ///
+    /// Chains built only by feeding always pass, unless their order was changed afterwards, but
+    /// chains loaded from files or built from counts may not. The first violation found is
+    /// returned.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if !self.map.contains_key(&vec!(None; self.order)) {
+            return Err(ValidationError::MissingStartState)
+        }
+        for map in iter::once(&self.map).chain(self.tags.values()) {
+            for (state, states) in map {
+                if state.len() != self.order {
+                    return Err(ValidationError::WrongStateLength {
+                        expected: self.order,
+                        found: state.len(),
+                    })
+                }
+                if states.values().any(|&count| count == 0) {
+                    return Err(ValidationError::ZeroCount)
+                }
+            }
+            for (state, states) in map {
+                for token in states.keys().filter(|token| token.is_some()) {
+                    let mut next = state.clone();
+                    self.advance(&mut next, token.clone());
+                    if !map.contains_key(&next) {
+                        return Err(ValidationError::DanglingTransition)
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the number of fed collections of tokens of each length, keyed by length. Empty
+    /// collections are not fed, and so are not counted.
+    pub fn length_distribution(&self) -> &BTreeMap<usize, usize> {
+        &self.lengths
+    }
+
+    /// Gets the number of states with at least one recorded continuation.
+    pub fn len(&self) -> usize {
+        self.map.values().filter(|states| !states.is_empty()).count()
+    }
+
+    /// Gets the total number of tokens fed into the chain, counting repeated tokens each time.
+    pub fn token_count(&self) -> usize {
+        self.map.values().flat_map(|states| states.iter())
+            .filter(|&(token, _)| token.is_some()).map(|(_, &count)| count)
+            .fold(0, usize::saturating_add)
+    }
+
+    /// Determines whether the given token has been fed into the chain.
+    pub fn contains_token(&self, token: &T) -> bool {
+        self.known.contains(token)
+    }
+
+    /// Gets the number of distinct tokens fed into the chain. This can be kept as a checkpoint and
+    /// later passed to `vocab_added_since`.
+    pub fn vocab_len(&self) -> usize {
+        self.vocabulary.len()
+    }
+
+    /// Gets the tokens first fed into the chain after the given checkpoint, a value previously
+    /// returned by `vocab_len`, in the order in which they were first fed.
+    pub fn vocab_added_since(&self, checkpoint: usize) -> Vec<&T> {
+        self.vocabulary.iter().skip(checkpoint).map(|token| &**token).collect()
+    }
+
+    /// Gets every distinct token fed into the chain, in the order in which each was first fed,
+    /// along with the number of times it was fed in total.
+    pub fn vocabulary(&self) -> impl Iterator<Item = (&T, usize)> + '_ {
+        let mut frequencies: HashMap<&P, usize> = HashMap::new();
+        for (token, count) in self.map.values().flat_map(|states| states.iter()) {
+            if let Some(token) = token.as_ref() {
+                let total = frequencies.entry(token).or_insert(0);
+                *total = total.saturating_add(*count);
+            }
+        }
+        self.vocabulary.iter()
+            .map(move |token| (&**token, frequencies.get(token).cloned().unwrap_or(0)))
+    }
+
+    /// Builds a copy of the chain restricted to the tokens for which `keep` returns true, e.g. to
+    /// strip rare or sensitive tokens from a trained model. States holding any other token are
+    /// dropped, and transitions into any other token become ends of collections, so generation
+    /// stops where a dropped token would have appeared. Settings are kept, but start states, tags
+    /// and n-grams holding dropped tokens are removed, and the distribution of fed lengths, which
+    /// no longer applies, is left empty.
+    ///
+    /// ```
+    /// use markov::Chain;
+    ///
+    /// let mut chain = Chain::new();
+    /// chain.feed_str("I like cats").feed_str("I like dogs").feed_str("I like dogs");
+    /// let common: Vec<_> = chain.vocabulary().filter(|&(_, n)| n > 1).map(|(t, _)| t.clone())
+    ///                           .collect();
+    /// let restricted = chain.restricted(|token| common.contains(token));
+    /// assert!(!restricted.contains_token(&"cats".to_owned()));
+    /// ```
+    pub fn restricted<F>(&self, mut keep: F) -> GenericChain<T, P, H> where F: FnMut(&T) -> bool {
+        let kept: HashSet<&P> = self.vocabulary.iter().filter(|token| keep(token)).collect();
+        let all_kept = |tokens: &[Token<P>]| {
+            tokens.iter().all(|token| token.as_ref().is_none_or(|token| kept.contains(token)))
+        };
+        let restrict = |map: &Transitions<P, H>| {
+            let mut restricted = Transitions::default();
+            for (state, states) in map.iter().filter(|&(state, _)| all_kept(state)) {
+                let mut continuations = HashMap::default();
+                for (token, &count) in states {
+                    let token = token.clone().filter(|token| kept.contains(token));
+                    let total = continuations.entry(token).or_insert(0usize);
+                    *total = total.saturating_add(count);
+                }
+                restricted.insert(state.clone(), continuations);
+            }
+            restricted
+        };
+        let mut map = restrict(&self.map);
+        map.entry(vec![None; self.order]).or_default();
+        let vocabulary: Vec<P> = self.vocabulary.iter().filter(|token| kept.contains(token))
+                                     .cloned().collect();
+        GenericChain {
+            map,
+            order: self.order,
+            dead_end: self.dead_end,
+            bidirectional: self.bidirectional,
+            smoothing: self.smoothing,
+            max_count: self.max_count,
+            starts: self.starts.iter().filter(|&(state, _)| all_kept(state)).cloned().collect(),
+            tags: self.tags.iter().map(|(tag, map)| (tag.clone(), restrict(map))).collect(),
+            known: vocabulary.iter().cloned().collect(),
+            vocabulary,
+            ngram_len: self.ngram_len,
+            ngrams: self.ngrams.iter()
+                        .filter(|ngram| ngram.iter().all(|token| kept.contains(token)))
+                        .cloned().collect(),
+            seen: self.seen.clone(),
+            ..GenericChain::default()
+        }
+    }
+
+    /// Gets the number of collections of tokens fed into the chain, excluding empty collections.
+    pub fn fed_sequences_count(&self) -> usize {
+        self.lengths.values().fold(0, |total, &count| total.saturating_add(count))
+    }
+
+    /// Determines whether or not the chain is empty. A chain is considered empty if nothing has
+    /// been fed into it, i.e. if generation cannot begin from the start of a collection. Generating
+    /// from an empty chain produces empty collections, and its iterators yield nothing.
+    pub fn is_empty(&self) -> bool {
+        self.map.get(&vec!(None; self.order))
+            .is_none_or(|states| states.values().all(|&count| count == 0))
+    }
+
+
+    /// Feeds the chain a collection of tokens. This operation is O(n) where n is the number of
+    /// tokens to be fed into the chain. The tokens may come from any iterator, and are consumed
+    /// as they are produced rather than collected first, unless `deduplicate` is enabled. An empty
+    /// collection leaves the chain unchanged, and is not counted by `fed_sequences_count`.
+    pub fn feed<S: IntoIterator<Item = T>>(&mut self, tokens: S) -> &mut GenericChain<T, P, H> {
+        self.feed_tokens(tokens.into_iter().map(P::new));
+        self
+    }
+
+    /// Feeds the chain a collection of tokens like `feed`, keeping only the tokens for which
+    /// `keep` returns true, e.g. to drop URLs, mentions or stop words. The remaining tokens are
+    /// fed as if they had been adjacent. To filter text as it is fed, use a `FilteredTokenizer`.
+    pub fn feed_filtered<S, F>(&mut self, tokens: S, mut keep: F) -> &mut GenericChain<T, P, H>
+        where S: IntoIterator<Item = T>, F: FnMut(&T) -> bool {
+        self.feed(tokens.into_iter().filter(|token| keep(token)))
+    }
+
+    /// Feeds the chain a collection of tokens like `feed`, additionally recording its transitions
+    /// under the given tag. Generation can then be limited to the collections fed under particular
+    /// tags with `generate_tagged`. Tagged transitions share their tokens with the rest of the
+    /// chain, so many tags cost far less memory than one chain per tag.
+    pub fn feed_tagged<S>(&mut self, tokens: S, tag: &str) -> &mut GenericChain<T, P, H>
+        where S: IntoIterator<Item = T> {
+        let tokens: Vec<_> = tokens.into_iter().map(|token| {
+            self.known.get(&token).cloned().unwrap_or_else(|| P::new(token))
+        }).collect();
+        if !self.feed_tokens(tokens.iter().cloned()) { return self }
+        let mut curs = vec!(None; self.order);
+        let map = self.tags.entry(tag.to_owned()).or_default();
+        for token in tokens.into_iter().map(Some).chain(Some(None)) {
+            map.entry(curs.clone()).or_default().add_capped(token.clone(), self.max_count);
+            if self.order > 0 {
+                curs.remove(0);
+                curs.push(token);
+            }
+        }
+        self
+    }
+
+    /// Gets the tags that collections have been fed under, in arbitrary order.
+    pub fn tags(&self) -> impl Iterator<Item = &str> + '_ {
+        self.tags.keys().map(|tag| &tag[..])
+    }
+
+    /// Feeds the chain many collections of tokens at once. Equal tokens within the batch share a
+    /// single allocation, which makes this cheaper in memory than feeding each collection
+    /// separately when the collections have a vocabulary in common.
+    pub fn feed_all<I, S>(&mut self, sequences: I) -> &mut GenericChain<T, P, H>
+        where I: IntoIterator<Item = S>, S: IntoIterator<Item = T> {
+        let mut interned: HashSet<P> = HashSet::new();
+        let mut intern = |token: T| match interned.get(&token) {
+            Some(token) => token.clone(),
+            None => {
+                let token = P::new(token);
+                interned.insert(token.clone());
+                token
+            },
+        };
+        for tokens in sequences {
+            self.feed_tokens(tokens.into_iter().map(&mut intern));
+        }
+        self
+    }
+
+    /// Feeds the chain a sequence of already shared tokens.
+    fn feed_tokens<I: Iterator<Item = P>>(&mut self, tokens: I) -> bool {
+        if let Some(ref mut seen) = self.seen {
+            let tokens: Vec<_> = tokens.collect();
+            let mut hasher = DefaultHasher::new();
+            tokens.hash(&mut hasher);
+            if tokens.is_empty() || !seen.insert(hasher.finish()) { return false }
+            return self.feed_unseen_tokens(tokens.into_iter())
+        }
+        self.feed_unseen_tokens(tokens)
+    }
+
+    /// Feeds the chain a collection of tokens like `feed_tokens`, without checking for duplicates.
+    fn feed_unseen_tokens<I: Iterator<Item = P>>(&mut self, tokens: I) -> bool {
+        let mut tokens = tokens.peekable();
+        if tokens.peek().is_none() { return false }
+        let mut predecessors = if self.bidirectional {
+            let index = self.indexes.get_mut().unwrap().predecessors.take();
+            Some(index.unwrap_or_else(|| self.build_predecessor_index()))
+        } else {
+            None
+        };
+        *self.indexes.get_mut().unwrap() = Indexes::default();
+        let mut curs = vec!(None; self.order);
+        let mut len = 0;
+        let mut window = Vec::new();
+        for token in tokens {
+            if self.ngram_len > 0 {
+                window.push(token.clone());
+                if window.len() > self.ngram_len { window.remove(0); }
+                if window.len() == self.ngram_len { self.ngrams.insert(window.clone()); }
+            }
+            let token = Some(token);
+            self.learn(&token);
+            self.map.entry(curs.clone()).or_default().add_capped(token.clone(), self.max_count);
+            if let Some(ref mut index) = predecessors {
+                let mut next = curs.clone();
+                self.advance(&mut next, token.clone());
+                if self.order > 0 {
+                    let previous = index.entry(next).or_default();
+                    let count = previous.entry(curs.clone()).or_insert(0usize);
+                    *count = count.saturating_add(1);
+                }
+            }
+            self.advance(&mut curs, token);
+            len += 1;
+        }
+        self.map.entry(curs).or_default().add_capped(None, self.max_count);
+        let count = self.lengths.entry(len).or_insert(0);
+        *count = count.saturating_add(1);
+        self.indexes.get_mut().unwrap().predecessors = predecessors;
+        true
+    }
+
+    /// Records a transition observed the given number of times, as read from a saved chain.
+    fn add_count(&mut self, state: Vec<Option<T>>, token: Option<T>, count: usize) {
+        let state = state.into_iter().map(|token| token.map(|token| self.intern(token))).collect();
+        let token = token.map(|token| self.intern(token));
+        let total = self.map.entry(state).or_default().entry(token).or_insert(0);
+        *total = total.saturating_add(count);
+    }
+
+    /// Gets the shared copy of a token that has been fed before, or adds it to the vocabulary.
+    fn intern(&mut self, token: T) -> P {
+        if let Some(token) = self.known.get(&token) { return token.clone() }
+        let token = P::new(token);
+        self.learn(&Some(token.clone()));
+        token
+    }
+
+    /// Adds the token to the vocabulary if it has not been fed before.
+    fn learn(&mut self, token: &Token<P>) {
+        if let Some(token) = token {
+            if self.known.insert(token.clone()) {
+                self.vocabulary.push(token.clone());
+            }
+        }
+    }
+
+    /// Advances the cursor past the next token, dropping the oldest token from the context.
+    fn advance(&self, curs: &mut Vec<Token<P>>, next: Token<P>) {
+        if self.order > 0 {
+            curs.remove(0);
+            curs.push(next);
+        }
+    }
+
+    /// Builds an index from each token to the observed states ending in it.
+    fn build_suffix_index(&self) -> SuffixIndex<P, H> {
+        let mut index: SuffixIndex<P, H> = HashMap::default();
+        for (state, states) in &self.map {
+            let weight = sum_counts(states.values());
+            if let Some(Some(token)) = state.last() {
+                if weight > 0 {
+                    index.entry(token.clone()).or_default().push((state.clone(), weight));
+                }
+            }
+        }
+        index
+    }
+
+    /// Builds an index from each state to the observed states leading into it. At order zero,
+    /// every token leads from and to the same state, so the index is empty.
+    fn build_predecessor_index(&self) -> PredecessorIndex<P, H> {
+        let mut index: PredecessorIndex<P, H> = HashMap::default();
+        if self.order == 0 { return index }
+        for (state, states) in &self.map {
+            for (token, &count) in states {
+                if token.is_some() && count > 0 {
+                    let mut next = state.clone();
+                    self.advance(&mut next, token.clone());
+                    index.entry(next).or_default().insert(state.clone(), count);
+                }
+            }
+        }
+        index
+    }
+
+    /// Walks backwards from the state to the start of a collection, returning the tokens that
+    /// precede the last token of the state, in order. Each step chooses a state leading into the
+    /// current one in proportion to how often the transition was observed.
+    fn walk_back<R: Rng>(&self, state: &[Token<P>], rng: &mut R) -> Vec<P> {
+        let mut ret = Vec::new();
+        self.with_predecessor_index(|index| {
+            let mut curs = state;
+            while let Some(states) = index.get(curs) {
+                let i = choose_weighted(states.values().map(|&count| count as u64), rng).unwrap();
+                let previous = states.keys().nth(i).unwrap();
+                match previous.last() {
+                    Some(Some(token)) => ret.push(token.clone()),
+                    _ => break,
+                }
+                curs = previous;
+            }
+        });
+        ret.reverse();
+        ret
+    }
+
+    /// Calls the function with the suffix index, building the index first if necessary.
+    fn with_suffix_index<F, U>(&self, f: F) -> U where F: FnOnce(&SuffixIndex<P, H>) -> U {
+        if let Some(index) = self.indexes.read().unwrap().suffixes.as_ref() {
+            return f(index)
+        }
+        let mut indexes = self.indexes.write().unwrap();
+        if indexes.suffixes.is_none() {
+            indexes.suffixes = Some(self.build_suffix_index());
+        }
+        f(indexes.suffixes.as_ref().unwrap())
+    }
+
+    /// Calls the function with the predecessor index, building the index first if necessary.
+    fn with_predecessor_index<F, U>(&self, f: F) -> U
+        where F: FnOnce(&PredecessorIndex<P, H>) -> U {
+        if let Some(index) = self.indexes.read().unwrap().predecessors.as_ref() {
+            return f(index)
+        }
+        let mut indexes = self.indexes.write().unwrap();
+        if indexes.predecessors.is_none() {
+            indexes.predecessors = Some(self.build_predecessor_index());
+        }
+        f(indexes.predecessors.as_ref().unwrap())
+    }
+
+    /// Determines whether generation can start from the given token.
+    fn knows_token(&self, token: &P) -> bool {
+        if self.order == 0 {
+            self.map.get(&Vec::new())
+                    .is_some_and(|states| states.contains_key(&Some(token.clone())))
+        } else {
+            self.with_suffix_index(|index| index.contains_key(token))
+        }
+    }
+
+    /// Gets the state from which generation continues after the given token, or `None` if the
+    /// token was never fed into the chain. At orders above zero, this is one of the states ending
+    /// in the token, chosen in proportion to how often it was observed.
+    fn token_state<R: Rng>(&self, token: &P, rng: &mut R) -> Option<Vec<Token<P>>> {
+        if self.order == 0 {
+            return if self.knows_token(token) { Some(Vec::new()) } else { None }
+        }
+        self.with_suffix_index(|index| {
+            let states = index.get(token)?;
+            let i = choose_weighted(states.iter().map(|&(_, weight)| weight), rng)?;
+            Some(states[i].0.clone())
+        })
+    }
+
+    /// Looks up the continuations of the current state. If the state has none, this applies the
+    /// dead-end policy, either returning `None` to end generation or moving the cursor to a
+    /// random state chosen in proportion to how often it was observed.
+    fn successors<R: Rng>(&self, curs: &mut Vec<Token<P>>, rng: &mut R)
+        -> Option<&HashMap<Token<P>, usize, H>> {
+        match self.map.get(curs) {
+            Some(states) if states.values().any(|&count| count > 0) => return Some(states),
+            _ => if self.dead_end == DeadEndPolicy::Terminate { return None },
+        }
+        let i = choose_weighted(self.map.values().map(|states| sum_counts(states.values())), rng)?;
+        let (state, states) = self.map.iter().nth(i).unwrap();
+        *curs = state.clone();
+        Some(states)
+    }
+
+    /// Chooses the state in which `generate` begins, among the registered start states if there
+    /// are any.
+    fn start<R: Rng>(&self, rng: &mut R) -> Vec<Token<P>> {
+        if self.starts.is_empty() { return vec!(None; self.order) }
+        let candidates: Vec<_> = self.starts.iter().map(|&(_, weight)| (None::<&T>, weight))
+                                            .collect();
+        self.starts[WeightedSampler.choose(&candidates, rng)].0.clone()
+    }
+
+    /// Chooses the next token from the continuations of the current state, applying the chain's
+    /// smoothing.
+    fn choose<R: Rng>(&self, states: &HashMap<Token<P>, usize, H>, rng: &mut R) -> Token<P> {
+        if self.smoothing > 0.0 {
+            states.next_smoothed(self.smoothing, &self.vocabulary, rng)
+        } else {
+            states.next(rng)
+        }
+    }
+
+    /// Generates a collection of tokens from the chain. This operation is O(mn) where m is the
+    /// length of the generated collection, and n is the number of possible states from a given
+    /// state. This returns an empty vector if the chain is empty.
+    pub fn generate(&self) -> Vec<P> {
+        self.try_generate().unwrap_or_default()
+    }
+
+    /// Generates a collection of tokens from the chain, as with `generate`, returning an error
+    /// instead of an empty vector if the chain is empty.
+    pub fn try_generate(&self) -> Result<Vec<P>, GenerateError> {
+        if self.is_empty() { return Err(GenerateError::Empty) }
+        Ok(self.generate_with_rng(&mut thread_rng()))
+    }
+
+    /// Generates a collection of tokens from the chain using the given random number generator.
+    /// This is useful for reproducible generation, e.g. with a seeded generator in tests.
+    pub fn generate_with_rng<R: Rng>(&self, rng: &mut R) -> Vec<P> {
+        let mut curs = self.start(rng);
+        let mut ret: Vec<_> = curs.iter().filter_map(|token| token.clone()).collect();
+        loop {
+            let next = self.successors(&mut curs, rng).and_then(|states| self.choose(states, rng));
+            match next {
+                Some(next) => {
+                    self.advance(&mut curs, Some(next.clone()));
+                    ret.push(next);
+                },
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// Generates a collection of tokens from the chain like `generate`, along with the probability
+    /// with which each token was chosen given the tokens before it, taking the chain's smoothing
+    /// into account. Generation always begins from the start of a collection, ignoring any start
+    /// states.
+    pub fn generate_traced(&self) -> Vec<(P, f64)> {
+        let rng = &mut thread_rng();
+        let mut ret = Vec::new();
+        let mut curs = vec!(None; self.order);
+        loop {
+            let next = self.successors(&mut curs, rng).and_then(|states| {
+                let next = self.choose(states, rng)?;
+                let total = sum_counts(states.values()) as f64 +
+                            self.smoothing * (self.known.len() + 1) as f64;
+                let count = states.get(&Some(next.clone())).cloned().unwrap_or(0) as f64;
+                Some((next, (count + self.smoothing) / total))
+            });
+            match next {
+                Some((next, p)) => {
+                    self.advance(&mut curs, Some(next.clone()));
+                    ret.push((next, p));
+                },
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// Generates a collection of tokens from the chain, choosing each token with the given
+    /// sampler rather than in proportion to how often it was observed. As with `generate`, the
+    /// collection begins with one of the registered start states, if there are any, which is
+    /// chosen in proportion to its weight rather than by the sampler.
+    pub fn generate_with_sampler<S>(&self, sampler: &mut S) -> Vec<P> where S: Sampler<T> {
+        let rng = &mut thread_rng();
+        let mut curs = self.start(rng);
+        let mut ret: Vec<_> = curs.iter().filter_map(|token| token.clone()).collect();
+        loop {
+            let next = self.successors(&mut curs, rng).and_then(|states| {
+                let tokens: Vec<_> = states.iter().filter(|&(_, &count)| count > 0).collect();
+                let candidates: Vec<_> = tokens.iter()
+                                               .map(|&(token, &count)| (token.as_deref(), count))
+                                               .collect();
+                tokens[sampler.choose(&candidates, rng)].0.clone()
+            });
+            match next {
+                Some(next) => {
+                    self.advance(&mut curs, Some(next.clone()));
+                    ret.push(next);
+                },
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// Generates a collection of tokens from the chain, aiming for a length drawn from the
+    /// distribution of fed lengths. Until the target length is reached, the end of the collection
+    /// is only chosen if no other continuation exists, and once it is reached, the collection ends
+    /// as soon as possible. This counteracts the tendency of generated collections to be shorter
+    /// than those fed. This returns an empty vector if nothing has been fed into the chain.
+    pub fn generate_with_length_prior(&self) -> Vec<P> {
+        let rng = &mut thread_rng();
+        let target = match choose_weighted(self.lengths.values().map(|&count| count as u64), rng) {
+            Some(i) => *self.lengths.keys().nth(i).unwrap(),
+            None => return Vec::new(),
+        };
+        let mut ret = Vec::new();
+        let mut curs = vec!(None; self.order);
+        loop {
+            let next = self.successors(&mut curs, rng).and_then(|states| {
+                let short = ret.len() < target;
+                let can_end = states.get(&None).is_some_and(|&count| count > 0);
+                if !short && can_end { return None }
+                let can_continue = states.iter()
+                                         .any(|(token, &count)| token.is_some() && count > 0);
+                let tokens: Vec<_> = states.iter().filter(|&(token, &count)| {
+                    count > 0 && !(short && can_continue && token.is_none())
+                }).collect();
+                let candidates: Vec<_> = tokens.iter()
+                                               .map(|&(token, &count)| (token.as_deref(), count))
+                                               .collect();
+                tokens[WeightedSampler.choose(&candidates, rng)].0.clone()
+            });
+            match next {
+                Some(next) => {
+                    self.advance(&mut curs, Some(next.clone()));
+                    ret.push(next);
+                },
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// Generates a collection of tokens that continues on from the given tokens, which are
+    /// typically the end of a previously generated collection. Generation starts from the state
+    /// formed by the last tokens, and the collection is not ended before at least one token has
+    /// been chosen. If that state has no continuation other than ending, this generates a fresh
+    /// collection from the start state instead.
+    fn generate_after(&self, tail: &[P]) -> Vec<P> {
+        let rng = &mut thread_rng();
+        let tail = &tail[tail.len().saturating_sub(self.order)..];
+        let mut curs: Vec<Token<P>> = vec!(None; self.order - tail.len());
+        curs.extend(tail.iter().cloned().map(Some));
+        let first = self.map.get(&curs).and_then(|states| {
+            let tokens: Vec<_> = states.iter()
+                                       .filter(|&(token, &count)| token.is_some() && count > 0)
+                                       .collect();
+            if tokens.is_empty() { return None }
+            let candidates: Vec<_> = tokens.iter()
+                                           .map(|&(token, &count)| (token.as_deref(), count))
+                                           .collect();
+            tokens[WeightedSampler.choose(&candidates, rng)].0.clone()
+        });
+        let mut ret = match first {
+            Some(first) => {
+                self.advance(&mut curs, Some(first.clone()));
+                vec![first]
+            },
+            None => return self.generate_with_rng(rng),
+        };
+        loop {
+            let next = self.successors(&mut curs, rng).and_then(|states| self.choose(states, rng));
+            match next {
+                Some(next) => {
+                    self.advance(&mut curs, Some(next.clone()));
+                    ret.push(next);
+                },
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// Generates a collection of tokens from only the transitions fed under any of the given tags
+    /// with `feed_tagged`, combining their counts. Unknown tags are ignored, so this returns an
+    /// empty vector if none of the tags are known.
+    pub fn generate_tagged(&self, tags: &[&str]) -> Vec<P> {
+        let rng = &mut thread_rng();
+        let maps: Vec<_> = tags.iter().filter_map(|&tag| self.tags.get(tag)).collect();
+        let mut ret = Vec::new();
+        let mut curs = vec!(None; self.order);
+        loop {
+            let mut states: HashMap<Token<P>, usize> = HashMap::new();
+            for map in &maps {
+                for (token, &count) in map.get(&curs).into_iter().flatten() {
+                    let total = states.entry(token.clone()).or_insert(0);
+                    *total = total.saturating_add(count);
+                }
+            }
+            if states.is_empty() { break }
+            match states.next(rng) {
+                Some(next) => {
+                    self.advance(&mut curs, Some(next.clone()));
+                    ret.push(next);
+                },
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// Generates a collection of tokens from the chain, starting with the given token. This
+    /// operation is O(mn) where m is the length of the generated collection, and n is the number
+    /// of possible states from a given state. This returns an empty vector if the token is not
+    /// found. At higher orders, generation continues from a state ending in the token, chosen in
+    /// proportion to how often it was observed. The states are looked up in an index that is built
+    /// on first use and rebuilt after the chain is fed again.
+    pub fn generate_from_token(&self, token: T) -> Vec<P> {
+        self.try_generate_from_token(token).unwrap_or_default()
+    }
+
+    /// Generates a collection of tokens from the chain, starting with the given token, as with
+    /// `generate_from_token`, returning an error instead of an empty vector if the token is not
+    /// found.
+    pub fn try_generate_from_token(&self, token: T) -> Result<Vec<P>, GenerateError> {
+        let token = P::new(token);
+        let rng = &mut thread_rng();
+        let mut curs = self.token_state(&token, rng).ok_or(GenerateError::UnknownToken)?;
+        let mut ret = vec![token];
+        loop {
+            let next = self.successors(&mut curs, rng).and_then(|states| self.choose(states, rng));
+            match next {
+                Some(next) => {
+                    self.advance(&mut curs, Some(next.clone()));
+                    ret.push(next);
+                },
+                None => break,
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Generates a collection of tokens from the chain, ending with the given token, by walking
+    /// backwards from a state ending in the token to the start of a collection. This returns an
+    /// empty vector if the token is not found. At order zero, tokens do not depend on those before
+    /// them, so the collection consists of only the token.
+    pub fn generate_to_token(&self, token: T) -> Vec<P> {
+        let token = P::new(token);
+        let rng = &mut thread_rng();
+        let state = match self.token_state(&token, rng) {
+            Some(state) => state,
+            None => return Vec::new(),
+        };
+        let mut ret = self.walk_back(&state, rng);
+        ret.push(token);
+        ret
+    }
+
+    /// Generates the tokens leading up to the given token, by walking backwards from a state
+    /// ending in the token. Unlike `generate_to_token`, the tokens are in reverse: the given token
+    /// comes first, followed by the token before it, and so on back to the start of a collection.
+    /// This returns an empty vector if the token is not found.
+    pub fn generate_backwards_from_token(&self, token: T) -> Vec<P> {
+        let mut ret = self.generate_to_token(token);
+        ret.reverse();
+        ret
+    }
+
+    /// Generates a collection of tokens from the chain that passes through the given token, by
+    /// walking both backwards and forwards from a state ending in the token. This returns an
+    /// empty vector if the token is not found.
+    pub fn generate_through_token(&self, token: T) -> Vec<P> {
+        let token = P::new(token);
+        let rng = &mut thread_rng();
+        let mut curs = match self.token_state(&token, rng) {
+            Some(curs) => curs,
+            None => return Vec::new(),
+        };
+        let mut ret = self.walk_back(&curs, rng);
+        ret.push(token);
+        loop {
+            let next = self.successors(&mut curs, rng).and_then(|states| self.choose(states, rng));
+            match next {
+                Some(next) => {
+                    self.advance(&mut curs, Some(next.clone()));
+                    ret.push(next);
+                },
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// Generates a collection of tokens from the chain, starting with one of several weighted seed
+    /// tokens. Seeds that are not found in the chain, or that have a non-positive weight, are
+    /// skipped, and the starting token is chosen among the rest with probability proportional to
+    /// its weight. This returns an empty vector if none of the seeds can be used.
+    pub fn generate_from_any(&self, seeds: &[(T, f64)]) -> Vec<P> where T: Clone {
+        let known: Vec<_> = seeds.iter().filter(|&&(ref token, weight)| {
+            weight > 0.0 && self.knows_token(&P::new(token.clone()))
+        }).collect();
+        if known.is_empty() { return Vec::new() }
+        let total: f64 = known.iter().map(|&&(_, weight)| weight).sum();
+        let mut cap = thread_rng().gen::<f64>() * total;
+        for &&(ref token, weight) in &known {
+            if cap < weight {
+                return self.generate_from_token(token.clone())
+            }
+            cap -= weight;
+        }
+        self.generate_from_token(known[known.len() - 1].0.clone())
+    }
+
+    /// Generates a collection of tokens containing every one of the given tokens, by generating
+    /// repeatedly until a collection contains them all. This returns `None` if no such collection
+    /// was generated within the given number of attempts.
+    pub fn generate_containing(&self, tokens: &[T], max_attempts: usize) -> Option<Vec<P>> {
+        (0..max_attempts).map(|_| self.generate()).find(|generated| {
+            tokens.iter().all(|token| generated.iter().any(|t| **t == *token))
+        })
+    }
+
+    /// Generates a collection of tokens from the chain, only ever choosing tokens that satisfy the
+    /// given predicate. This allows generation to be constrained by one field of a structured
+    /// token, e.g. only choosing words spoken by a particular speaker. Generation ends early if no
+    /// permitted continuation exists. This operation is O(mn) where m is the length of the
+    /// generated collection, and n is the number of possible states from a given state.
+    pub fn generate_where<F>(&self, predicate: F) -> Vec<P> where F: Fn(&T) -> bool {
+        let mut ret = Vec::new();
+        let mut curs = vec!(None; self.order);
+        let rng = &mut thread_rng();
+        loop {
+            let next = self.successors(&mut curs, rng)
+                           .and_then(|states| states.next_where(&predicate, rng));
+            match next {
+                Some(next) => {
+                    self.advance(&mut curs, Some(next.clone()));
+                    ret.push(next);
+                },
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// Searches for the most probable collections of tokens using a beam search, rather than
+    /// sampling. This returns up to `options.width` complete collections along with their
+    /// length-normalized log-probabilities, best first. Generation is deterministic, except that
+    /// ties are broken arbitrarily.
+    pub fn beam_search(&self, options: BeamOptions) -> Vec<(Vec<P>, f64)> {
+        let mut beams = vec![(Vec::new(), vec!(None; self.order), 0.0)];
+        let mut complete = Vec::new();
+        while !beams.is_empty() {
+            let mut candidates = Vec::new();
+            for (tokens, curs, log_prob) in beams {
+                let states = match self.map.get(&curs) {
+                    Some(states) => states,
+                    None => continue,
+                };
+                let total = sum_counts(states.values()) as f64;
+                for (token, &count) in states.iter().filter(|&(_, &count)| count > 0) {
+                    let log_prob = log_prob + (count as f64 / total).ln();
+                    match *token {
+                        None => {
+                            let score = options.score(log_prob, tokens.len() + 1);
+                            complete.push((tokens.clone(), score));
+                        },
+                        Some(ref next) if tokens.len() < options.max_len => {
+                            let mut tokens = tokens.clone();
+                            tokens.push(next.clone());
+                            let mut curs = curs.clone();
+                            self.advance(&mut curs, Some(next.clone()));
+                            candidates.push((tokens, curs, log_prob));
+                        },
+                        Some(_) => {},
+                    }
+                }
+            }
+            candidates.sort_by(|a, b| {
+                options.score(b.2, b.0.len()).total_cmp(&options.score(a.2, a.0.len()))
+            });
+            candidates.truncate(options.width);
+            beams = candidates;
+        }
+        complete.sort_by(|a, b| b.1.total_cmp(&a.1));
+        complete.truncate(options.width);
+        complete
+    }
+
+    /// Produces an iterator over a single generated collection of tokens, yielding each token as
+    /// it is chosen. The walk can be paused at any point with `GeneratorState::save` and
+    /// continued later with `resume`.
+    pub fn generator(&self) -> GeneratorState<'_, T, P, H> {
+        GeneratorState { chain: self, curs: vec!(None; self.order), done: false }
+    }
+
+    /// Produces an iterator that generates a single collection of tokens lazily, yielding each
+    /// token as it is chosen and ending at the end of the collection. Unlike `generate`, nothing
+    /// is collected, so tokens can be consumed as they are produced.
+    pub fn generate_iter(&self) -> impl Iterator<Item = P> + '_ {
+        self.generator()
+    }
+
+    /// Resumes a walk from a cursor produced by `GeneratorState::save`. This returns `None` if the
+    /// cursor does not correspond to a state in this chain, e.g. because it was saved from a chain
+    /// of a different order.
+    pub fn resume(&self, cursor: Vec<Option<T>>) -> Option<GeneratorState<'_, T, P, H>> {
+        let curs: Vec<_> = cursor.into_iter().map(|token| token.map(P::new)).collect();
+        if !self.map.contains_key(&curs) { return None }
+        Some(GeneratorState { chain: self, curs, done: false })
+    }
+
+    /// Produces an infinite iterator of generated token collections. If the chain is empty, the
+    /// iterator yields nothing instead.
+    pub fn iter(&self) -> InfiniteChainIterator<'_, T, P, H> {
+        InfiniteChainIterator { chain: self, continuous: false, previous: Vec::new() }
+    }
+
+    /// Produces an infinite iterator of tokens, starting a new collection from the start state
+    /// whenever one ends. This yields nothing if the chain is empty.
+    pub fn stream(&self) -> impl Iterator<Item = P> + '_ {
+        self.iter().flatten()
+    }
+
+    /// Produces an infinite iterator of tokens like `stream`, yielding the given boundary token
+    /// after each generated collection. This yields nothing if the chain is empty.
+    pub fn stream_with_boundary(&self, boundary: T) -> impl Iterator<Item = P> + '_ {
+        let boundary = P::new(boundary);
+        self.iter().flat_map(move |tokens| {
+            tokens.into_iter().chain(iter::once(boundary.clone()))
+        })
+    }
+
+    /// Generates exactly the specified number of tokens, continuing with a new collection from the
+    /// start state whenever one ends. This returns an empty vector if the chain is empty.
+    pub fn generate_exact(&self, n: usize) -> Vec<P> {
+        self.stream().take(n).collect()
+    }
+
+    /// Produces an iterator for the specified number of generated token collections, or for none if
+    /// the chain is empty.
+    pub fn iter_for(&self, size: usize) -> SizedChainIterator<'_, T, P, H> {
+        SizedChainIterator { chain: self, size, continuous: false, previous: Vec::new() }
+    }
+}
+
+impl<T, P, H> Default for GenericChain<T, P, H>
+    where T: Chainable, P: SharedPtr<T>, H: BuildHasher + Default {
+    fn default() -> GenericChain<T, P, H> {
+        GenericChain {
+            map: {
+                let mut map = HashMap::default();
+                map.insert(vec!(None; 1), HashMap::default());
+                map
+            },
+            order: 1,
+            dead_end: DeadEndPolicy::Terminate,
+            bidirectional: false,
+            smoothing: 0.0,
+            max_count: usize::MAX,
+            starts: Vec::new(),
+            lengths: BTreeMap::new(),
+            tags: HashMap::new(),
+            vocabulary: Vec::new(),
+            known: HashSet::default(),
+            ngram_len: 0,
+            ngrams: HashSet::default(),
+            seen: None,
+            indexes: RwLock::new(Indexes::default()),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, P, H> MarkovChain<T> for GenericChain<T, P, H>
+    where T: Chainable, P: SharedPtr<T>, H: BuildHasher + Default {
+    type Token = P;
+    type Iter<'a> = InfiniteChainIterator<'a, T, P, H> where T: 'a, P: 'a, H: 'a;
+    type SizedIter<'a> = SizedChainIterator<'a, T, P, H> where T: 'a, P: 'a, H: 'a;
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn get_order(&self) -> usize {
+        self.get_order()
+    }
+
+    fn feed<S: IntoIterator<Item = T>>(&mut self, tokens: S) -> &mut GenericChain<T, P, H> {
+        self.feed(tokens)
+    }
+
+    fn generate(&self) -> Vec<P> {
+        self.generate()
+    }
+
+    fn generate_from_token(&self, token: T) -> Vec<P> {
+        self.generate_from_token(token)
+    }
+
+    fn iter(&self) -> InfiniteChainIterator<'_, T, P, H> {
+        self.iter()
+    }
+
+    fn iter_for(&self, size: usize) -> SizedChainIterator<'_, T, P, H> {
+        self.iter_for(size)
+    }
+}
+
+impl<T, P, H> Extend<Vec<T>> for GenericChain<T, P, H>
+    where T: Chainable, P: SharedPtr<T>, H: BuildHasher + Default {
+    fn extend<I: IntoIterator<Item = Vec<T>>>(&mut self, sequences: I) {
+        self.feed_all(sequences);
+    }
+}
+
+impl<T, P, H> FromIterator<Vec<T>> for GenericChain<T, P, H>
+    where T: Chainable, P: SharedPtr<T>, H: BuildHasher + Default {
+    fn from_iter<I: IntoIterator<Item = Vec<T>>>(sequences: I) -> GenericChain<T, P, H> {
+        let mut chain = GenericChain::default();
+        chain.extend(sequences);
+        chain
+    }
+}
+
+impl<P, H> GenericChain<String, P, H> where P: SharedPtr<String>, H: BuildHasher + Default {
+    /// Feeds a string of text into the chain. The string is split into words on any Unicode
+    /// whitespace, so tabs, newlines, and runs of spaces never produce empty tokens. Empty and
+    /// whitespace-only strings produce no tokens at all, and so leave the chain unchanged.
+    pub fn feed_str(&mut self, string: &str) -> &mut GenericChain<String, P, H> {
+        self.feed_str_with(string, &WhitespaceTokenizer)
+    }
+
+    /// Feeds a string of text into the chain, splitting it into tokens with the given tokenizer.
+    pub fn feed_str_with<K>(&mut self, string: &str, tokenizer: &K)
+        -> &mut GenericChain<String, P, H>
+        where K: Tokenizer {
+        self.feed(tokenizer.tokenize(string))
+    }
+
+    /// Feeds a string of text into the chain, splitting it on whitespace like `feed_str`, but
+    /// feeding each sentence as a separate collection, just as `feed_file` feeds each line. A
+    /// sentence ends with any token ending in `.`, `!`, `?` or `…`, possibly followed by closing
+    /// quotes or brackets, so abbreviations such as "Mr." also end a sentence.
+    pub fn feed_text(&mut self, text: &str) -> &mut GenericChain<String, P, H> {
+        self.feed_text_with(text, &WhitespaceTokenizer)
+    }
+
+    /// Feeds a string of text into the chain one sentence at a time as with `feed_text`,
+    /// splitting it into tokens with the given tokenizer. With a tokenizer that splits off
+    /// punctuation, such as `PunctuationTokenizer`, the punctuation ends the sentence it follows.
+    pub fn feed_text_with<K>(&mut self, text: &str, tokenizer: &K)
+        -> &mut GenericChain<String, P, H>
+        where K: Tokenizer {
+        let mut sentence = Vec::new();
+        for token in tokenizer.tokenize(text) {
+            let ends = token.trim_end_matches(|c| "\"')]}”’»".contains(c))
+                            .ends_with(['.', '!', '?', '…']);
+            sentence.push(token);
+            if ends {
+                self.feed(sentence.drain(..));
+            }
+        }
+        self.feed(sentence)
+    }
+
+    /// Feeds a properly formatted file into the chain. This file should be formatted such that
+    /// each line is a new sentence. Punctuation may be included if it is desired.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file cannot be opened or read, e.g. if it is not valid UTF-8. Use
+    /// `try_feed_file` to handle such errors.
+    pub fn feed_file<Q: AsRef<Path>>(&mut self, path: Q) -> &mut GenericChain<String, P, H> {
+        self.try_feed_file(path).unwrap()
+    }
+
+    /// Feeds a properly formatted file into the chain as with `feed_file`, returning an error
+    /// instead of panicking if the file cannot be opened or read. Lines read before the error
+    /// remain in the chain.
+    pub fn try_feed_file<Q: AsRef<Path>>(&mut self, path: Q)
+        -> io::Result<&mut GenericChain<String, P, H>> {
+        self.try_feed_file_with(path, &WhitespaceTokenizer)
+    }
+
+    /// Feeds a properly formatted file into the chain as with `feed_file`, splitting each line
+    /// into tokens with the given tokenizer, e.g. a `FilteredTokenizer` that drops unwanted
+    /// tokens as the file is streamed in.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file cannot be opened or read, as with `feed_file`.
+    pub fn feed_file_with<Q, K>(&mut self, path: Q, tokenizer: &K)
+        -> &mut GenericChain<String, P, H>
+        where Q: AsRef<Path>, K: Tokenizer {
+        self.try_feed_file_with(path, tokenizer).unwrap()
+    }
+
+    /// Feeds a properly formatted file into the chain as with `feed_file_with`, returning an error
+    /// instead of panicking if the file cannot be opened or read.
+    pub fn try_feed_file_with<Q, K>(&mut self, path: Q, tokenizer: &K)
+        -> io::Result<&mut GenericChain<String, P, H>>
+        where Q: AsRef<Path>, K: Tokenizer {
+        let file = File::open(path)?;
+        self.try_feed_reader_with(BufReader::new(file), tokenizer)
+    }
+
+    /// Feeds properly formatted text from a reader into the chain. As with `feed_file`, each line
+    /// is treated as a new sentence, and empty or whitespace-only lines are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if reading fails, e.g. on text that is not valid UTF-8. Use `try_feed_reader` to
+    /// handle such errors.
+    pub fn feed_reader<R: BufRead>(&mut self, reader: R) -> &mut GenericChain<String, P, H> {
+        self.try_feed_reader(reader).unwrap()
+    }
+
+    /// Feeds properly formatted text from a reader into the chain as with `feed_reader`, stopping
+    /// at the first read error instead of panicking. Lines read before the error remain in the
+    /// chain.
+    pub fn try_feed_reader<R: BufRead>(&mut self, reader: R)
+        -> io::Result<&mut GenericChain<String, P, H>> {
+        self.try_feed_reader_with(reader, &WhitespaceTokenizer)
+    }
+
+    /// Feeds properly formatted text from a reader into the chain as with `feed_reader`, splitting
+    /// each line into tokens with the given tokenizer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if reading fails, as with `feed_reader`.
+    pub fn feed_reader_with<R, K>(&mut self, reader: R, tokenizer: &K)
+        -> &mut GenericChain<String, P, H>
+        where R: BufRead, K: Tokenizer {
+        self.try_feed_reader_with(reader, tokenizer).unwrap()
+    }
+
+    /// Feeds properly formatted text from a reader into the chain as with `feed_reader_with`,
+    /// stopping at the first read error instead of panicking.
+    pub fn try_feed_reader_with<R, K>(&mut self, reader: R, tokenizer: &K)
+        -> io::Result<&mut GenericChain<String, P, H>>
+        where R: BufRead, K: Tokenizer {
+        for line in reader.lines() {
+            self.feed_str_with(&line?, tokenizer);
+        }
+        Ok(self)
+    }
+
+    /// Feeds the named field of each line of a JSON lines file into the chain, e.g. the text of
+    /// each message in an exported chat log. Each line must hold a JSON object, and the field is
+    /// fed as with `feed_str`. Empty lines, and lines on which the field is missing or null, are
+    /// skipped. This fails with an error of kind `InvalidData` on a malformed line or a field that
+    /// does not hold a string, leaving the lines before it in the chain.
+    pub fn feed_jsonl<R: BufRead>(&mut self, reader: R, field: &str)
+        -> io::Result<&mut GenericChain<String, P, H>> {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() { continue }
+            if let Some(text) = json::string_field(&line, field)? {
+                self.feed_str(&text);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Feeds the named column of a CSV table into the chain, as with `feed_str`. The first row of
+    /// the table must name the columns, and fields are read as described in RFC 4180, so quoted
+    /// fields may hold commas and line breaks. Rows too short to hold the column are skipped. This
+    /// fails with an error of kind `InvalidData` if no column has the name or a quoted field is
+    /// never closed, leaving the rows before it in the chain.
+    pub fn feed_csv<R: BufRead>(&mut self, reader: R, column: &str)
+        -> io::Result<&mut GenericChain<String, P, H>> {
+        let mut reader = csv::Reader::new(reader);
+        let header = reader.read_record()?.unwrap_or_default();
+        let i = header.iter().position(|name| name == column).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData,
+                           format!("Found no column named {}.", column))
+        })?;
+        while let Some(record) = reader.read_record()? {
+            if let Some(text) = record.get(i) {
+                self.feed_str(text);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Feeds a properly formatted file into the chain as with `feed_file`, passing each line
+    /// through the cleaner first, e.g. to strip HTML tags. This is only available with the
+    /// `markup` feature enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file cannot be opened or read, as with `feed_file`.
+    #[cfg(feature = "markup")]
+    pub fn feed_file_cleaned<Q, C>(&mut self, path: Q, cleaner: C)
+        -> &mut GenericChain<String, P, H>
+        where Q: AsRef<Path>, C: markup::Cleaner {
+        self.try_feed_file_cleaned(path, cleaner).unwrap()
+    }
+
+    /// Feeds a properly formatted file into the chain as with `feed_file_cleaned`, returning an
+    /// error instead of panicking if the file cannot be opened or read. This is only available
+    /// with the `markup` feature enabled.
+    #[cfg(feature = "markup")]
+    pub fn try_feed_file_cleaned<Q, C>(&mut self, path: Q, cleaner: C)
+        -> io::Result<&mut GenericChain<String, P, H>>
+        where Q: AsRef<Path>, C: markup::Cleaner {
+        let file = File::open(path)?;
+        self.try_feed_reader_cleaned(BufReader::new(file), cleaner)
+    }
+
+    /// Feeds properly formatted text from a reader into the chain as with `feed_reader`, passing
+    /// each line through the cleaner first. Lines that are empty after cleaning are skipped. This
+    /// is only available with the `markup` feature enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if reading fails, as with `feed_reader`.
+    #[cfg(feature = "markup")]
+    pub fn feed_reader_cleaned<R, C>(&mut self, reader: R, cleaner: C)
+        -> &mut GenericChain<String, P, H>
+        where R: BufRead, C: markup::Cleaner {
+        self.try_feed_reader_cleaned(reader, cleaner).unwrap()
+    }
+
+    /// Feeds properly formatted text from a reader into the chain as with `feed_reader_cleaned`,
+    /// stopping at the first read error instead of panicking. This is only available with the
+    /// `markup` feature enabled.
+    #[cfg(feature = "markup")]
+    pub fn try_feed_reader_cleaned<R, C>(&mut self, reader: R, mut cleaner: C)
+        -> io::Result<&mut GenericChain<String, P, H>>
+        where R: BufRead, C: markup::Cleaner {
+        for line in reader.lines() {
+            self.feed_str(&cleaner.clean(&line?));
+        }
+        Ok(self)
+    }
+
+    /// Feeds every file in a directory and its subdirectories into the chain, as with
+    /// `feed_file`. Files are visited in sorted order, and symbolic links to directories are not
+    /// followed. Rather than panicking, this returns every path that could not be read along with
+    /// the error encountered, e.g. for files that are not valid UTF-8. Lines read from a file
+    /// before an error remain in the chain.
+    pub fn feed_dir<Q: AsRef<Path>>(&mut self, path: Q) -> Vec<(PathBuf, io::Error)> {
+        self.feed_dir_filtered(path, |_| true)
+    }
+
+    /// Feeds the files in a directory and its subdirectories for which the filter returns `true`
+    /// into the chain, as with `feed_dir`. This can be used to select files by extension.
+    pub fn feed_dir_filtered<Q, F>(&mut self, path: Q, filter: F) -> Vec<(PathBuf, io::Error)>
+        where Q: AsRef<Path>, F: Fn(&Path) -> bool {
+        let mut errors = Vec::new();
+        let mut dirs = vec![path.as_ref().to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let entries = fs::read_dir(&dir).and_then(|e| e.collect::<io::Result<Vec<_>>>());
+            let mut entries = match entries {
+                Ok(entries) => entries,
+                Err(e) => { errors.push((dir, e)); continue },
+            };
+            entries.sort_by_key(|entry| entry.path());
+            let mut subdirs = Vec::new();
+            for entry in entries {
+                let path = entry.path();
+                let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                if is_dir {
+                    subdirs.push(path);
+                } else if path.is_file() && filter(&path) {
+                    if let Err(e) = self.try_feed_file(&path) {
+                        errors.push((path, e));
+                    }
+                }
+            }
+            dirs.extend(subdirs.into_iter().rev());
+        }
+        errors
+    }
+
+    /// Feeds a properly formatted file into the chain, as with `feed_file`, calling the callback
+    /// with the progress made so far after each line. This returns a summary of everything fed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file cannot be opened or read, as with `feed_file`.
+    pub fn feed_file_with_progress<Q, F>(&mut self, path: Q, callback: F) -> FeedProgress
+        where Q: AsRef<Path>, F: FnMut(&FeedProgress) {
+        self.try_feed_file_with_progress(path, callback).unwrap()
+    }
+
+    /// Feeds a properly formatted file into the chain as with `feed_file_with_progress`,
+    /// returning an error instead of panicking if the file cannot be opened or read.
+    pub fn try_feed_file_with_progress<Q, F>(&mut self, path: Q, callback: F)
+        -> io::Result<FeedProgress> where Q: AsRef<Path>, F: FnMut(&FeedProgress) {
+        let file = File::open(path)?;
+        self.try_feed_reader_with_progress(BufReader::new(file), callback)
+    }
+
+    /// Feeds properly formatted text from a reader into the chain, as with `feed_reader`, calling
+    /// the callback with the progress made so far after each line. This returns a summary of
+    /// everything fed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if reading fails, as with `feed_reader`.
+    pub fn feed_reader_with_progress<R, F>(&mut self, reader: R, callback: F) -> FeedProgress
+        where R: BufRead, F: FnMut(&FeedProgress) {
+        self.try_feed_reader_with_progress(reader, callback).unwrap()
+    }
+
+    /// Feeds properly formatted text from a reader into the chain as with
+    /// `feed_reader_with_progress`, stopping at the first read error instead of panicking. The
+    /// callback has been called for every line fed before the error.
+    pub fn try_feed_reader_with_progress<R, F>(&mut self, mut reader: R, mut callback: F)
+        -> io::Result<FeedProgress> where R: BufRead, F: FnMut(&FeedProgress) {
+        let mut progress = FeedProgress::default();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let bytes = reader.read_line(&mut line)?;
+            if bytes == 0 { break }
+            let tokens = WhitespaceTokenizer.tokenize(&line);
+            progress.bytes += bytes;
+            progress.lines += 1;
+            progress.tokens += tokens.len();
+            self.feed(tokens);
+            callback(&progress);
+        }
+        Ok(progress)
+    }
+
+    /// Feeds properly formatted text from a file into the chain, as with
+    /// `feed_reader_with_checkpoints`.
+    pub fn feed_file_with_checkpoints<Q, F>(&mut self, path: Q, every: usize, checkpoint: F)
+        -> io::Result<FeedProgress>
+        where Q: AsRef<Path>, F: FnMut(&Self, &FeedProgress) -> io::Result<()> {
+        self.feed_reader_with_checkpoints(BufReader::new(File::open(path)?), every, checkpoint)
+    }
+
+    /// Feeds properly formatted text from a reader into the chain, as with `feed_reader`, calling
+    /// the checkpoint with the chain and the progress made so far after every `every` lines, e.g.
+    /// to `save` the chain during a long run. If the checkpoint fails, feeding stops and its error
+    /// is returned. To resume after a crash, load the last saved chain and feed the rest of the
+    /// text, skipping the number of lines given by the progress at that checkpoint.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `every` is zero.
+    pub fn feed_reader_with_checkpoints<R, F>(&mut self, mut reader: R, every: usize,
+                                              mut checkpoint: F) -> io::Result<FeedProgress>
+        where R: BufRead, F: FnMut(&Self, &FeedProgress) -> io::Result<()> {
+        assert!(every > 0, "Checkpoints must be at least one line apart.");
+        let mut progress = FeedProgress::default();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let bytes = reader.read_line(&mut line)?;
+            if bytes == 0 { break }
+            let tokens = WhitespaceTokenizer.tokenize(&line);
+            progress.bytes += bytes;
+            progress.lines += 1;
+            progress.tokens += tokens.len();
+            self.feed(tokens);
+            if progress.lines % every == 0 {
+                checkpoint(self, &progress)?;
+            }
+        }
+        Ok(progress)
+    }
+
+    /// Converts the output of generate(...) on a String chain to a single String.
+    fn vec_to_string(vec: Vec<P>) -> String {
+        Self::vec_to_string_with(vec, &WhitespaceDetokenizer)
+    }
+
+    /// Converts the output of generate(...) on a String chain to a single String using the given
+    /// detokenizer.
+    fn vec_to_string_with<D>(vec: Vec<P>, detokenizer: &D) -> String
+        where D: Detokenizer {
+        let tokens: Vec<_> = vec.iter().map(|s| &s[..]).collect();
+        detokenizer.detokenize(&tokens)
+    }
+
+    /// Generates a random string of text.
+    pub fn generate_str(&self) -> String {
+        Self::vec_to_string(self.generate())
+    }
+
+    /// Generates a random string of text, joining the generated tokens with the given
+    /// detokenizer.
+    pub fn generate_str_with<D>(&self, detokenizer: &D) -> String where D: Detokenizer {
+        Self::vec_to_string_with(self.generate(), detokenizer)
+    }
+
+    /// Generates a string of text of exactly the specified number of words, continuing with a new
+    /// sentence whenever one ends. This returns an empty string if the chain is empty.
+    pub fn generate_str_exact(&self, n: usize) -> String {
+        Self::vec_to_string(self.generate_exact(n))
+    }
+
+    /// Generates a paragraph of the specified number of independently generated sentences. Each
+    /// sentence is capitalized and, unless it already ends with `.`, `!` or `?`, given a period.
+    /// This returns an empty string if the chain is empty.
+    pub fn generate_paragraph(&self, sentences: usize) -> String {
+        Self::join_sentences((0..sentences).map(|_| self.generate()))
+    }
+
+    /// Generates a paragraph like `generate_paragraph`, except that each sentence after the first
+    /// continues on from the last words of the sentence before it, so that the paragraph flows
+    /// rather than consisting of unrelated sentences.
+    pub fn generate_linked_paragraph(&self, sentences: usize) -> String {
+        let mut previous: Vec<P> = Vec::new();
+        Self::join_sentences((0..sentences).map(|i| {
+            previous = if i == 0 { self.generate() } else { self.generate_after(&previous) };
+            previous.clone()
+        }))
+    }
+
+    /// Joins generated sentences into a paragraph, capitalizing and punctuating each sentence.
+    fn join_sentences<I>(sentences: I) -> String where I: Iterator<Item = Vec<P>> {
+        let sentences: Vec<_> = sentences.filter(|sentence| !sentence.is_empty()).map(|sentence| {
+            let sentence = Self::vec_to_string(sentence);
+            let mut chars = sentence.chars();
+            let mut ret: String = chars.next().into_iter().flat_map(char::to_uppercase).collect();
+            ret.push_str(chars.as_str());
+            if !ret.ends_with(['.', '!', '?']) {
+                ret.push('.');
+            }
+            ret
+        }).collect();
+        sentences.join(" ")
+    }
+
+    /// Generates a random string of text containing every one of the given words, by generating
+    /// repeatedly. This returns `None` if no such string was generated within the given number of
+    /// attempts.
+    pub fn generate_str_containing(&self, words: &[&str], max_attempts: usize) -> Option<String> {
+        let words: Vec<_> = words.iter().map(|&word| word.to_owned()).collect();
+        self.generate_containing(&words, max_attempts).map(Self::vec_to_string)
+    }
+
+    /// Generates a random string of text ending with the desired token. This returns an empty
+    /// string if the token is not found.
+    pub fn generate_str_to_token(&self, string: &str) -> String {
+        Self::vec_to_string(self.generate_to_token(string.to_owned()))
+    }
+
+    /// Generates a random string of text containing the desired token. This returns an empty
+    /// string if the token is not found.
+    pub fn generate_str_through_token(&self, string: &str) -> String {
+        Self::vec_to_string(self.generate_through_token(string.to_owned()))
+    }
+
+    /// Generates a random string of text starting with the desired token. This returns an empty
+    /// string if the token is not found.
+    pub fn generate_str_from_token(&self, string: &str) -> String {
+        Self::vec_to_string(self.generate_from_token(string.to_owned()))
+    }
+
+    /// Generates a random string of text starting with one of several weighted seed words, as
+    /// described in `generate_from_any`. This returns an empty string if none of the seeds can be
+    /// used.
+    pub fn generate_str_from_any(&self, seeds: &[(&str, f64)]) -> String {
+        let seeds: Vec<_> = seeds.iter().map(|&(seed, weight)| (seed.to_owned(), weight)).collect();
+        Self::vec_to_string(self.generate_from_any(&seeds))
+    }
+
+    /// Produces an infinite iterator of generated strings.
+    pub fn str_iter(&self) -> InfiniteChainStringIterator<'_, P, H> {
+        let vec_to_string: fn(Vec<P>) -> String = Self::vec_to_string;
+        self.iter().map(vec_to_string)
+    }
+
+    /// Produces a sized iterator of generated strings.
+    pub fn str_iter_for(&self, size: usize) -> SizedChainStringIterator<'_, P, H> {
+        let vec_to_string: fn(Vec<P>) -> String = Self::vec_to_string;
+        self.iter_for(size).map(vec_to_string)
+    }
+
+    /// Produces an infinite iterator of generated strings, joining tokens with the given
+    /// detokenizer.
+    pub fn str_iter_with<'a, D>(&'a self, detokenizer: &'a D)
+        -> DetokenizedChainIterator<'a, InfiniteChainIterator<'a, String, P, H>, D>
+        where D: Detokenizer {
+        DetokenizedChainIterator { iter: self.iter(), detokenizer }
+    }
+
+    /// Produces a sized iterator of generated strings, joining tokens with the given detokenizer.
+    pub fn str_iter_for_with<'a, D>(&'a self, size: usize, detokenizer: &'a D)
+        -> DetokenizedChainIterator<'a, SizedChainIterator<'a, String, P, H>, D>
+        where D: Detokenizer {
+        DetokenizedChainIterator { iter: self.iter_for(size), detokenizer }
+    }
+}
+
+/// An iterator over a Markov chain of strings that joins each generated collection of tokens
+/// with a detokenizer.
+pub struct DetokenizedChainIterator<'a, I, D: 'a> {
+    iter: I,
+    detokenizer: &'a D,
+}
+
+impl<'a, I, D, P> Iterator for DetokenizedChainIterator<'a, I, D>
+    where I: Iterator<Item = Vec<P>>, D: Detokenizer + 'a, P: SharedPtr<String> {
+    type Item = String;
+    fn next(&mut self) -> Option<String> {
+        let detokenizer = self.detokenizer;
+        self.iter.next().map(|vec| GenericChain::<String, P>::vec_to_string_with(vec, detokenizer))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// A sized iterator over a Markov chain of strings.
+pub type SizedChainStringIterator<'a, P = Rc<String>, H = RandomState> =
+Map<SizedChainIterator<'a, String, P, H>, fn(Vec<P>) -> String>;
+
+/// A sized iterator over a Markov chain.
+pub struct SizedChainIterator<'a, T, P = Rc<T>, H = RandomState>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: 'a {
+    chain: &'a GenericChain<T, P, H>,
+    size: usize,
+    continuous: bool,
+    previous: Vec<P>,
+}
+
+impl<'a, T, P, H> SizedChainIterator<'a, T, P, H>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: BuildHasher + Default + 'a {
+    /// Makes each generated collection after the first continue on from the last tokens of the
+    /// collection before it, so that successive sentences read as connected text.
+    pub fn continuous(mut self) -> SizedChainIterator<'a, T, P, H> {
+        self.continuous = true;
+        self
+    }
+}
+
+impl<'a, T, P, H> Iterator for SizedChainIterator<'a, T, P, H>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: BuildHasher + Default + 'a {
+    type Item = Vec<P>;
+    fn next(&mut self) -> Option<Vec<P>> {
+        if self.size > 0 && !self.chain.is_empty() {
+            self.size -= 1;
+            Some(next_collection(self.chain, self.continuous, &mut self.previous))
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.chain.is_empty() { (0, Some(0)) } else { (self.size, Some(self.size)) }
+    }
+}
+
+impl<'a, T, P, H> FusedIterator for SizedChainIterator<'a, T, P, H>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: BuildHasher + Default + 'a {}
+
+
+/// An infinite iterator over a Markov chain of strings.
+pub type InfiniteChainStringIterator<'a, P = Rc<String>, H = RandomState> =
+Map<InfiniteChainIterator<'a, String, P, H>, fn(Vec<P>) -> String>;
+
+/// An infinite iterator over a Markov chain.
+pub struct InfiniteChainIterator<'a, T, P = Rc<T>, H = RandomState>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: 'a {
+    chain: &'a GenericChain<T, P, H>,
+    continuous: bool,
+    previous: Vec<P>,
+}
+
+impl<'a, T, P, H> InfiniteChainIterator<'a, T, P, H>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: BuildHasher + Default + 'a {
+    /// Makes each generated collection after the first continue on from the last tokens of the
+    /// collection before it, so that successive sentences read as connected text.
+    pub fn continuous(mut self) -> InfiniteChainIterator<'a, T, P, H> {
+        self.continuous = true;
+        self
+    }
+}
+
+impl<'a, T, P, H> Iterator for InfiniteChainIterator<'a, T, P, H>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: BuildHasher + Default + 'a {
+    type Item = Vec<P>;
+    fn next(&mut self) -> Option<Vec<P>> {
+        if self.chain.is_empty() { return None }
+        Some(next_collection(self.chain, self.continuous, &mut self.previous))
+    }
+}
+
+impl<'a, T, P, H> FusedIterator for InfiniteChainIterator<'a, T, P, H>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: BuildHasher + Default + 'a {}
+
+/// Sums the given counts as a 64-bit integer, saturating rather than overflowing, so that the
+/// total of saturated counts stays meaningful even on 32-bit targets.
+pub(crate) fn sum_counts<'a, I: IntoIterator<Item = &'a usize>>(counts: I) -> u64 {
+    counts.into_iter().fold(0, |total, &count| total.saturating_add(count as u64))
+}
+
+/// Chooses the index of one of the given weights at random, in proportion to the weights, or
+/// `None` if they are all zero. Weights are summed with saturation rather than overflowing.
+pub(crate) fn choose_weighted<I, R>(weights: I, rng: &mut R) -> Option<usize>
+    where I: Iterator<Item = u64> + Clone, R: Rng {
+    let total = weights.clone().fold(0u64, |total, weight| total.saturating_add(weight));
+    if total == 0 { return None }
+    let cap = rng.gen_range(0, total);
+    let mut sum = 0u64;
+    weights.map(|weight| {
+        sum = sum.saturating_add(weight);
+        sum
+    }).position(|sum| sum > cap)
+}
+
+/// Generates the next collection for an iterator over the chain, continuing on from the previous
+/// collection if the iterator is continuous.
+fn next_collection<T, P, H>(chain: &GenericChain<T, P, H>, continuous: bool, previous: &mut Vec<P>)
+    -> Vec<P> where T: Chainable, P: SharedPtr<T>, H: BuildHasher + Default {
+    if !continuous {
+        return chain.generate()
+    }
+    let next = if previous.is_empty() { chain.generate() } else { chain.generate_after(previous) };
+    previous.clone_from(&next);
+    next
+}
+
+/// An in-progress walk through a Markov chain, yielding one token at a time.
+pub struct GeneratorState<'a, T, P = Rc<T>, H = RandomState>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: 'a {
+    chain: &'a GenericChain<T, P, H>,
+    curs: Vec<Token<P>>,
+    done: bool,
+}
+
+impl<'a, T, P, H> GeneratorState<'a, T, P, H>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: BuildHasher + Default + 'a {
+    /// Saves the position of this walk as a cursor of the last `order` tokens, with `None`
+    /// standing in for the start of the sequence. The cursor is plain data that can be stored
+    /// anywhere and passed to `resume` later, even in another process. Saving a finished walk
+    /// produces the start state, so resuming it begins a new walk.
+    pub fn save(&self) -> Vec<Option<T>> where T: Clone {
+        self.curs.iter().map(|token| token.as_ref().map(|token| (**token).clone())).collect()
+    }
+}
+
+impl<'a, T, P, H> Iterator for GeneratorState<'a, T, P, H>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: BuildHasher + Default + 'a {
+    type Item = P;
+    fn next(&mut self) -> Option<P> {
+        if self.done { return None }
+        let rng = &mut thread_rng();
+        let next = self.chain.successors(&mut self.curs, rng)
+                       .and_then(|states| self.chain.choose(states, rng));
+        match next {
+            Some(next) => {
+                self.chain.advance(&mut self.curs, Some(next.clone()));
+                Some(next)
+            },
+            None => {
+                self.done = true;
+                self.curs = vec!(None; self.chain.order);
+                None
+            },
+        }
+    }
+}
+
+impl<'a, T, P, H> FusedIterator for GeneratorState<'a, T, P, H>
+    where T: Chainable + 'a, P: SharedPtr<T> + 'a, H: BuildHasher + Default + 'a {}
+
+/// A collection of states for the Markov chain.
+trait States<T, P> {
+    /// Adds a state to this states collection.
+    fn add(&mut self, token: Token<P>) {
+        self.add_capped(token, usize::MAX)
+    }
+    /// Adds a state to this states collection, unless it has already been added `max` times.
+    fn add_capped(&mut self, token: Token<P>, max: usize);
+    /// Gets the next state from this collection of states. If every count is zero, the collection
+    /// is treated as terminal.
+    fn next<R: Rng>(&self, rng: &mut R) -> Token<P>;
+    /// Gets the next state from this collection of states, adding the given pseudo-count to every
+    /// token in the vocabulary and to the terminal state.
+    fn next_smoothed<R>(&self, smoothing: f64, vocabulary: &[P], rng: &mut R) -> Token<P>
+        where R: Rng;
+    /// Gets the next state from the states whose token satisfies the predicate. The terminal state
+    /// is always permitted, and is returned if no other state is.
+    fn next_where<F, R>(&self, predicate: &F, rng: &mut R) -> Token<P>
+        where F: Fn(&T) -> bool, R: Rng;
+}
+
+impl<T, P, H> States<T, P> for HashMap<Token<P>, usize, H>
+    where T: Chainable, P: SharedPtr<T>, H: BuildHasher {
+    fn add_capped(&mut self, token: Token<P>, max: usize) {
+        match self.entry(token) {
+            Occupied(mut e) => if *e.get() < max { *e.get_mut() += 1 },
+            Vacant(e) => { e.insert(1); },
+        }
+    }
+
+    fn next<R: Rng>(&self, rng: &mut R) -> Token<P> {
+        let i = choose_weighted(self.values().map(|&count| count as u64), rng);
+        i.and_then(|i| self.keys().nth(i).unwrap().clone())
+    }
+
+    fn next_smoothed<R>(&self, smoothing: f64, vocabulary: &[P], rng: &mut R) -> Token<P>
+        where R: Rng {
+        let unseen = vocabulary.iter().map(|token| Some(token.clone())).chain(Some(None))
+                               .filter(|token| !self.contains_key(token));
+        let total = sum_counts(self.values()) as f64 + smoothing * (vocabulary.len() + 1) as f64;
+        let mut cap = rng.gen::<f64>() * total;
+        let mut last = None;
+        for (key, &value) in self.iter() {
+            let weight = value as f64 + smoothing;
+            if cap < weight {
+                return key.clone()
+            }
+            cap -= weight;
+            last = key.clone();
+        }
+        for token in unseen {
+            if cap < smoothing {
+                return token
+            }
+            cap -= smoothing;
+            last = token;
+        }
+        last
+    }
+
+    fn next_where<F, R>(&self, predicate: &F, rng: &mut R) -> Token<P>
+        where F: Fn(&T) -> bool, R: Rng {
+        let permitted = |key: &Token<P>| key.as_ref().is_none_or(|token| predicate(token));
+        let counts = self.iter().map(|(key, &count)| if permitted(key) { count as u64 } else { 0 });
+        choose_weighted(counts, rng).and_then(|i| self.keys().nth(i).unwrap().clone())
+    }
+}
+#[cfg(test)]
+mod test {
+    use std::{env, fs, io, process};
+    use std::collections::HashMap;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+    use std::rc::Rc;
+    use rand::thread_rng;
+    use super::{Analysis, ArcChain, BeamOptions, Chain, ChainStats, DeadEndPolicy, FeedProgress};
+    use super::{GenerateError, MarkovChain, SeedError, ValidationError};
+    use sampler::GreedySampler;
+    use tokenizer::{PunctuationDetokenizer, PunctuationTokenizer, SeparatorDetokenizer};
+    use tokenizer::{FilteredTokenizer, SpanTokenizer, WhitespaceTokenizer};
+
+    #[test]
+    fn new() {
+        Chain::<u8>::new();
+        Chain::<String>::new();
+    }
+
+    #[test]
+    fn get_order() {
+        let mut chain = Chain::<u8>::new();
+        assert_eq!(chain.get_order(), 1);
+        assert_eq!(chain.order(3).get_order(), 3);
+    }
+
+    #[test]
+    fn validate_seed() {
+        let mut chain = Chain::new();
+        chain.order(2);
+        chain.feed(vec![3u8, 5, 10]);
+        assert_eq!(chain.validate_seed(&[3, 5]), Ok(()));
+        assert_eq!(chain.validate_seed(&[5, 3]), Err(SeedError::Unknown));
+        assert_eq!(chain.validate_seed(&[3]),
+                   Err(SeedError::WrongLength { expected: 2, found: 1 }));
+    }
+
+    #[test]
+    fn is_empty() {
+        let mut chain = Chain::new();
+        assert!(chain.is_empty());
+        assert!(chain.generate().is_empty());
+        assert_eq!(chain.iter().next(), None);
+        assert_eq!(chain.iter_for(3).size_hint(), (0, Some(0)));
+        assert_eq!(Chain::<String>::new().str_iter_for(3).count(), 0);
+        assert_eq!(chain.generate_iter().next(), None);
+        chain.feed(vec![1u8, 2, 3]);
+        assert!(!chain.is_empty());
+    }
+
+    #[test]
+    fn try_variants() {
+        let mut chain = Chain::new();
+        assert_eq!(chain.try_generate(), Err(GenerateError::Empty));
+        chain.feed(vec![1u8, 2, 3]);
+        assert_eq!(chain.try_generate().unwrap().len(), 3);
+        assert_eq!(chain.try_generate_from_token(2).unwrap().len(), 2);
+        assert_eq!(chain.try_generate_from_token(7), Err(GenerateError::UnknownToken));
+        let mut counts = HashMap::new();
+        counts.insert(vec![None, None], HashMap::new());
+        assert_eq!(Chain::<u8>::try_from_counts(counts, 1).err(),
+                   Some(ValidationError::WrongStateLength { expected: 1, found: 2 }));
+        let mut strings = Chain::new();
+        assert!(strings.try_feed_file("does/not/exist.txt").is_err());
+        assert!(strings.try_feed_reader(&b"I like\n\xff cats"[..]).is_err());
+        assert_eq!(strings.generate_str(), "I like");
+    }
+
+    #[test]
+    fn feed() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3, 5, 10]).feed(vec![5, 12]);
+    }
+
+    #[test]
+    fn feed_iterator() {
+        let mut chain = Chain::new();
+        chain.order(2);
+        chain.feed(3u8..6).feed(Vec::new());
+        let v: Vec<_> = chain.generate().into_iter().map(|v| *v).collect();
+        assert_eq!(v, vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn feed_all() {
+        let mut chain = Chain::new();
+        chain.feed_all(vec![vec![3u8], vec![5, 3]]);
+        let (state, _) = chain.map.get_key_value(&vec![Some(Rc::new(3))]).unwrap();
+        let (next, _) = chain.map[&vec![Some(Rc::new(5))]].iter().next().unwrap();
+        assert!(Rc::ptr_eq(state[0].as_ref().unwrap(), next.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn score() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5]).feed(vec![3, 10]);
+        assert_eq!(chain.score(&[3, 5]), 0.5f64.ln());
+        assert_eq!(chain.score(&[3, 12]), f64::NEG_INFINITY);
+        assert_eq!(chain.score(&[5]), f64::NEG_INFINITY);
+        let smoothed = chain.score_smoothed(&[3, 12], 1.0);
+        assert_eq!(smoothed, (3.0f64 / 6.0).ln() + (1.0f64 / 6.0).ln() + (1.0f64 / 4.0).ln());
+    }
+
+    #[test]
+    fn perplexity() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5]).feed(vec![3, 10]);
+        assert_eq!(chain.perplexity(vec![vec![3, 5]]), 2f64.powf(1.0 / 3.0));
+        assert_eq!(chain.perplexity(vec![vec![3, 5], vec![5]]), f64::INFINITY);
+        assert!(chain.perplexity_smoothed(vec![vec![3, 5], vec![5]], 1.0).is_finite());
+        assert!(chain.perplexity(vec![]).is_nan());
+    }
+
+    #[test]
+    fn smoothing() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5]).smoothing(1.0);
+        assert_eq!(chain.score(&[5, 3]), chain.score_smoothed(&[5, 3], 1.0));
+        assert!(chain.perplexity(vec![vec![5, 3]]).is_finite());
+        assert!((0..100).any(|_| chain.generate() != vec![Rc::new(3), Rc::new(5)]));
+    }
+
+    #[test]
+    fn start_states() {
+        let mut chain = Chain::new();
+        chain.feed(vec![1u8, 2, 3]).feed(vec![4, 5, 6]);
+        assert_eq!(chain.add_start_state(&[9], 1), Err(SeedError::Unknown));
+        chain.add_start_state(&[4], 1).unwrap();
+        let expected: Vec<_> = vec![4, 5, 6].into_iter().map(Rc::new).collect();
+        assert!((0..20).all(|_| chain.generate() == expected));
+        chain.clear_start_states();
+        assert!((0..100).any(|_| chain.generate() != expected));
+    }
+
+    #[test]
+    fn transitions() {
+        let mut chain = Chain::new();
+        chain.order(2).feed(vec![1u8, 2, 3]).feed(vec![1, 2]).feed(vec![4, 2, 3]);
+        let mut next: Vec<_> = chain.transitions(&[1, 2]).collect();
+        next.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(next, vec![(None, 0.5), (Some(&3), 0.5)]);
+        assert_eq!(chain.probability(&[], Some(&1)), 2.0 / 3.0);
+        assert_eq!(chain.probability(&[9, 1, 2], Some(&3)), 0.5);
+        assert_eq!(chain.probability(&[1, 2], Some(&4)), 0.0);
+        assert_eq!(chain.transitions(&[3, 3]).count(), 0);
+    }
+
+    #[test]
+    fn suggest() {
+        let mut chain = Chain::new();
+        chain.order(2).feed(vec![1u8, 2, 3]).feed(vec![4, 2, 5]).feed(vec![4, 2, 5]);
+        assert_eq!(chain.suggest(&[1, 2], 2), vec![(Rc::new(3), 1.0)]);
+        let expected = vec![(Rc::new(5), 2.0 / 3.0), (Rc::new(3), 1.0 / 3.0)];
+        assert_eq!(chain.suggest(&[9, 2], 2), expected);
+        assert_eq!(chain.suggest(&[9], 1), vec![(Rc::new(2), 0.25)]);
+        assert!(Chain::<u8>::new().suggest(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn generate_traced() {
+        let mut chain = Chain::new();
+        chain.feed(vec![1u8, 2]).feed(vec![1, 2]).feed(vec![1, 3]);
+        let trace = chain.generate_traced();
+        assert!(trace == vec![(Rc::new(1), 1.0), (Rc::new(2), 2.0 / 3.0)] ||
+                trace == vec![(Rc::new(1), 1.0), (Rc::new(3), 1.0 / 3.0)]);
+    }
+
+    #[test]
+    fn novelty() {
+        let mut chain = Chain::new();
+        chain.feed(vec![1u8, 2, 3]).track_ngrams(2).feed(vec![4, 5, 6]);
+        assert_eq!(chain.novelty(&[4, 5, 6]), 0.0);
+        assert_eq!(chain.novelty(&[4, 5, 7]), 0.5);
+        assert_eq!(chain.novelty(&[1, 2, 3]), 1.0);
+        assert_eq!(chain.novelty(&[4]), 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "N-gram tracking is not enabled.")]
+    fn novelty_untracked() {
+        Chain::<u8>::new().novelty(&[1]);
+    }
+
+    #[test]
+    fn entropy() {
+        let mut chain = Chain::new();
+        assert_eq!(chain.entropy(), 0.0);
+        chain.feed(vec![1u8, 2]);
+        assert_eq!(chain.entropy(), 0.0);
+        chain.feed(vec![1, 3]);
+        assert_eq!(chain.state_entropy(&[1]), 1.0);
+        assert_eq!(chain.state_entropy(&[2]), 0.0);
+        assert_eq!(chain.state_entropy(&[4]), 0.0);
+        assert_eq!(chain.entropy(), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn divergence() {
+        let chain = |tokens: Vec<u8>| {
+            let mut chain = Chain::new();
+            chain.feed(tokens);
+            chain
+        };
+        assert_eq!(chain(vec![1, 2]).divergence(&chain(vec![1, 2])), 0.0);
+        assert_eq!(chain(vec![1, 2]).divergence(&chain(vec![3, 4])), 1.0);
+        assert_eq!(chain(vec![1, 2]).divergence(&chain(vec![1, 3])), 0.5);
+        assert_eq!(chain(vec![1, 2]).divergence(&Chain::new()), 1.0);
+    }
+
+    #[test]
+    fn analyze() {
+        let mut chain = Chain::new();
+        assert_eq!(chain.analyze(), Analysis { terminal_states: vec![], unreachable: vec![] });
+        chain.feed(vec![1u8, 2]).order(2).feed(vec![3, 4]);
+        let analysis = chain.analyze();
+        assert_eq!(analysis.terminal_states, vec![vec![Some(&3), Some(&4)]]);
+        assert_eq!(analysis.unreachable, vec![&1, &2]);
+    }
+
+    #[test]
+    fn edges() {
+        let mut chain = Chain::new();
+        chain.feed(vec![1u8, 2]).feed(vec![1, 2]);
+        let mut edges: Vec<_> = chain.edges().collect();
+        edges.sort();
+        assert_eq!(edges, vec![(vec![None], Some(&1), 2), (vec![Some(&1)], Some(&2), 2),
+                               (vec![Some(&2)], None, 2)]);
+        assert_eq!(chain.sorted_edges(), edges);
+    }
+
+    #[test]
+    fn markov_chain_trait() {
+        fn feed_and_generate<C: MarkovChain<String>>(chain: &mut C) -> (String, usize) {
+            chain.feed_str("I like cats");
+            (chain.generate_str_from_token("like"), chain.iter_for(3).count())
+        }
+        let expected = ("like cats".to_owned(), 3);
+        assert_eq!(feed_and_generate(&mut Chain::new()), expected);
+        assert_eq!(feed_and_generate(&mut ArcChain::new()), expected);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut chain = Chain::new();
+        chain.order(2).feed_str("I like cats").feed_str("I like dogs");
+        let mut saved = Vec::new();
+        chain.save(&mut saved).unwrap();
+        let loaded: Chain<String> = Chain::load(&saved[..]).unwrap();
+        assert_eq!(loaded.sorted_edges(), chain.sorted_edges());
+        assert_eq!(loaded.get_order(), 2);
+        assert!(loaded.contains_token(&"like".to_owned()));
+        assert!(Chain::<u8>::load(&b"2\n1\tx\t\\-\t\\-\n"[..]).is_err());
+        assert!(Chain::<u8>::load(&b"2\n1\t1\t\\-\n"[..]).is_err());
+    }
+
+    #[test]
+    fn json() {
+        let mut chain = Chain::new();
+        chain.feed_str("I like cats").feed_str("I like");
+        let mut json = Vec::new();
+        chain.to_json(&mut json).unwrap();
+        assert_eq!(String::from_utf8(json).unwrap(), r#"{
+  "order": 1,
+  "states": [
+    {"state": [null], "next": {"I": 2}},
+    {"state": ["I"], "next": {"like": 2}},
+    {"state": ["cats"], "next": {}, "end": 1},
+    {"state": ["like"], "next": {"cats": 1}, "end": 1}
+  ]
+}
+"#);
+        chain.feed_str("say \"hi\"\\");
+        let mut json = Vec::new();
+        chain.to_json(&mut json).unwrap();
+        let read: Chain<String> = Chain::from_json(&json[..]).unwrap();
+        assert_eq!(read.sorted_edges(), chain.sorted_edges());
+        assert!(Chain::<String>::from_json(&br#"{"order": 1, "states": [{"state": []}]}"#[..])
+                    .is_err());
+    }
+
+    #[test]
+    fn custom_hasher() {
+        let mut chain: Chain<String, BuildHasherDefault<DefaultHasher>> = Chain::default();
+        chain.order(2).feed_str("I like cats and dogs");
+        assert_eq!(chain.generate_str(), "I like cats and dogs");
+        assert_eq!(chain.token_count(), 5);
+    }
+
+    #[test]
+    fn clone_and_collect() {
+        let chain: Chain<u8> = vec![vec![1, 2], vec![1, 3]].into_iter().collect();
+        let mut expected = Chain::new();
+        expected.feed(vec![1, 2]).feed(vec![1, 3]);
+        assert_eq!(chain, expected);
+        let mut clone = chain.clone();
+        assert_eq!(clone, chain);
+        clone.extend(vec![vec![4]]);
+        assert!(clone.contains_token(&4) && !chain.contains_token(&4));
+    }
+
+    #[test]
+    fn accessors() {
+        let mut chain = Chain::new();
+        assert_eq!((chain.len(), chain.token_count()), (0, 0));
+        chain.feed(vec![1u8, 2, 1]);
+        assert_eq!((chain.len(), chain.token_count()), (3, 3));
+        assert!(chain.contains_token(&2) && !chain.contains_token(&3));
+    }
+
+    #[test]
+    fn stats() {
+        let mut chain = Chain::new();
+        let ChainStats { states, transitions, vocabulary, tokens, branching, .. } = chain.stats();
+        assert_eq!((states, transitions, vocabulary, tokens, branching), (0, 0, 0, 0, 0.0));
+        chain.feed(vec![1u8, 2]).feed(vec![1, 3, 2]);
+        let stats = chain.stats();
+        assert_eq!((stats.states, stats.transitions, stats.vocabulary, stats.tokens), (4, 5, 3, 5));
+        assert_eq!(stats.branching, 1.25);
+        assert!(stats.memory > 0);
+    }
+
+    #[test]
+    fn tagged() {
+        let mut chain = Chain::new();
+        chain.feed_tagged(vec![1u8, 2], "a").feed_tagged(vec![1, 3], "b");
+        assert_eq!(chain.tags().count(), 2);
+        assert!((0..20).all(|_| chain.generate_tagged(&["a"]) == vec![Rc::new(1), Rc::new(2)]));
+        let expected = vec![Rc::new(1), Rc::new(3)];
+        assert!((0..100).any(|_| chain.generate_tagged(&["a", "b"]) == expected));
+        assert!(chain.generate_tagged(&["c"]).is_empty());
+        let mut untagged = Chain::new();
+        untagged.feed(vec![1u8, 2]).feed(vec![1, 3]);
+        assert_eq!(chain.map, untagged.map);
+    }
+
+    #[test]
+    fn generate() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5, 10]).feed(vec![5, 12]);
+        let v = chain.generate().into_iter().map(|v| *v).collect();
+        assert!([vec![3, 5, 10], vec![3, 5, 12], vec![5, 10], vec![5, 12]].contains(&v));
+    }
+
+    #[test]
+    fn generate_with_rng() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5, 10]);
+        let v: Vec<_> = chain.generate_with_rng(&mut thread_rng()).into_iter()
+                             .map(|v| *v).collect();
+        assert_eq!(v, vec![3, 5, 10]);
+    }
+
+    #[test]
+    fn generate_for_higher_order() {
+        let mut chain = Chain::new();
+        chain.order(2);
+        chain.feed(vec![3u8, 5, 10]).feed(vec![2, 3, 5, 12]);
+        let v = chain.generate().into_iter().map(|v| *v).collect();
+        assert!([vec![3, 5, 10], vec![3, 5, 12], vec![2, 3, 5, 10], vec![2, 3, 5, 12]].contains(&v));
+    }
+
+    #[test]
+    fn dead_end_terminate() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5, 10]);
+        chain.map.remove(&vec![Some(Rc::new(5))]);
+        let v: Vec<_> = chain.generate().into_iter().map(|v| *v).collect();
+        assert_eq!(v, vec![3, 5]);
+    }
+
+    #[test]
+    fn dead_end_restart() {
+        let mut chain = Chain::new();
+        chain.dead_end_policy(DeadEndPolicy::Restart);
+        chain.feed(vec![3u8, 5]);
+        chain.map.remove(&vec![None]);
+        for _ in 0..10 {
+            let v: Vec<_> = chain.generate().into_iter().map(|v| *v).collect();
+            assert!(v == vec![] || v == vec![5]);
+        }
+        chain.map.clear();
+        assert_eq!(chain.generate(), vec![]);
+    }
+
+    #[test]
+    fn generate_for_order_zero() {
+        let mut chain = Chain::new();
+        chain.order(0);
+        assert!(chain.is_empty());
+        chain.feed(vec![3u8, 3, 3]);
+        assert!(!chain.is_empty());
+        let v: Vec<_> = chain.generate().into_iter().map(|v| *v).collect();
+        assert!(v.iter().all(|&v| v == 3));
+        assert_eq!(chain.generate_from_token(3)[0], Rc::new(3));
+        assert_eq!(chain.generate_from_token(5), vec![]);
+        assert_eq!(chain.generator().save(), vec![]);
+    }
+
+    #[test]
+    fn generate_with_sampler() {
+        let mut chain = Chain::new();
+        chain.feed(vec![1u8, 2]).feed(vec![1, 2]).feed(vec![1, 3, 4]);
+        for _ in 0..10 {
+            let v: Vec<_> = chain.generate_with_sampler(&mut GreedySampler)
+                                 .into_iter().map(|v| *v).collect();
+            assert_eq!(v, vec![1, 2]);
+        }
+        chain.add_start_state(&[3], 1).unwrap();
+        let v: Vec<_> = chain.generate_with_sampler(&mut GreedySampler)
+                             .into_iter().map(|v| *v).collect();
+        assert_eq!(v, vec![3, 4]);
+    }
+
+    #[test]
+    fn length_distribution() {
+        let mut chain = Chain::new();
+        chain.feed(vec![1u8, 2]).feed(vec![3, 4]).feed(vec![5, 6, 7]).feed(vec![]);
+        let lengths: Vec<_> = chain.length_distribution().iter().collect();
+        assert_eq!(lengths, vec![(&2, &2), (&3, &1)]);
+    }
+
+    #[test]
+    fn generate_with_length_prior() {
+        let mut chain = Chain::new();
+        chain.feed(vec![1u8, 1, 1, 1]).feed(vec![1]);
+        for _ in 0..10 {
+            let len = chain.generate_with_length_prior().len();
+            assert!(len == 1 || len == 4);
+        }
+        assert_eq!(Chain::<u8>::new().generate_with_length_prior(), vec![]);
+    }
+
+    #[test]
+    fn vocab_added_since() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5, 3]);
+        let checkpoint = chain.vocab_len();
+        assert_eq!(checkpoint, 2);
+        chain.feed(vec![5, 10, 12, 10]);
+        assert_eq!(chain.vocab_added_since(checkpoint), vec![&10, &12]);
+        assert_eq!(chain.vocab_added_since(0), vec![&3, &5, &10, &12]);
+    }
 
-/// A generic [Markov chain](https://en.wikipedia.org/wiki/Markov_chain) for almost any type. This
-/// uses HashMaps internally, and so Eq and Hash are both required.
-#[derive(PartialEq, Debug)]
-pub struct Chain<T> where T: Chainable {
-    map: HashMap<Vec<Token<T>>, HashMap<Token<T>, usize>>,
-    order: usize,
-}
+    #[test]
+    fn empty_feeds() {
+        let mut chain = Chain::new();
+        chain.feed_str("").feed_str(" \t\n").feed(vec![]);
+        assert!(chain.is_empty());
+        assert_eq!(chain.fed_sequences_count(), 0);
+        chain.feed_reader("I like cats\n\n  \nI like dogs\n".as_bytes());
+        assert_eq!(chain.fed_sequences_count(), 2);
+        assert_eq!(chain.length_distribution().get(&0), None);
+    }
 
-impl<T> Chain<T> where T: Chainable {
-    /// Constructs a new Markov chain.
-    pub fn new() -> Chain<T> {
-        Chain {
-            map: {
-                let mut map = HashMap::new();
-                map.insert(vec!(None; 1), HashMap::new());
-                map
-            },
-            order: 1,
-        }
+    #[test]
+    fn generate_from_token() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5, 10]).feed(vec![5, 12]);
+        let v = chain.generate_from_token(5).into_iter().map(|v| *v).collect();
+        assert!([vec![5, 10], vec![5, 12]].contains(&v));
     }
 
-    /// Choose a specific Markov chain order. The order is the number of previous tokens to use
-    /// as the index into the map.
-    pub fn order(&mut self, order: usize) -> &mut Chain<T> {
-        assert!(order > 0);
-        self.order = order;
-        self.map.insert(vec!(None; self.order), HashMap::new());
-        self
+    #[test]
+    fn generate_from_unfound_token() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5, 10]).feed(vec![5, 12]);
+        let v: Vec<_> = chain.generate_from_token(9).into_iter().map(|v| *v).collect();
+        assert_eq!(v, vec![]);
     }
 
-    /// Determines whether or not the chain is empty. A chain is considered empty if nothing has
-    /// been fed into it.
-    pub fn is_empty(&self) -> bool {
-        self.map[&vec!(None; self.order)].is_empty()
+    #[test]
+    fn generate_from_token_higher_order() {
+        let mut chain = Chain::new();
+        chain.order(2).feed(vec![3u8, 5, 10]);
+        let v: Vec<_> = chain.generate_from_token(5).into_iter().map(|v| *v).collect();
+        assert_eq!(v, vec![5, 10]);
+        chain.feed(vec![4, 5, 12]);
+        let v = chain.generate_from_token(5).into_iter().map(|v| *v).collect();
+        assert!([vec![5, 10], vec![5, 12]].contains(&v));
+        let v: Vec<_> = chain.generate_from_token(4).into_iter().map(|v| *v).collect();
+        assert_eq!(v, vec![4, 5, 12]);
     }
 
+    #[test]
+    fn generate_to_token() {
+        let mut chain = Chain::new();
+        chain.order(2).feed(vec![3u8, 5, 10]).feed(vec![4, 5, 12]);
+        for _ in 0..10 {
+            let v: Vec<_> = chain.generate_to_token(10).into_iter().map(|v| *v).collect();
+            assert_eq!(v, vec![3, 5, 10]);
+            let v = chain.generate_to_token(5).into_iter().map(|v| *v).collect();
+            assert!([vec![3, 5], vec![4, 5]].contains(&v));
+        }
+        assert_eq!(chain.generate_to_token(9), vec![]);
+    }
 
-    /// Feeds the chain a collection of tokens. This operation is O(n) where n is the number of
-    /// tokens to be fed into the chain.
-    pub fn feed(&mut self, tokens: Vec<T>) -> &mut Chain<T> {
-        if tokens.is_empty() { return self }
-        let mut toks = vec!(None; self.order);
-        toks.extend(tokens.into_iter().map(|token| {
-            Some(Rc::new(token))
-        }));
-        toks.push(None);
-        for p in toks.windows(self.order + 1) {
-            self.map.entry(p[0..self.order].to_vec()).or_insert_with(HashMap::new);
-            self.map.get_mut(&p[0..self.order].to_vec()).unwrap().add(p[self.order].clone());
+    #[test]
+    fn bidirectional() {
+        let mut chain = Chain::new();
+        chain.bidirectional(true).order(2).feed(vec![3u8, 5, 10]);
+        let v: Vec<_> = chain.generate_backwards_from_token(10).into_iter().map(|v| *v).collect();
+        assert_eq!(v, vec![10, 5, 3]);
+        chain.feed(vec![4, 6, 10]).feed(vec![4, 6, 12]);
+        let mut expected = Chain::new();
+        expected.order(2).feed(vec![3u8, 5, 10]).feed(vec![4, 6, 10]).feed(vec![4, 6, 12]);
+        expected.with_predecessor_index(|index| {
+            assert_eq!(chain.indexes.read().unwrap().predecessors.as_ref(), Some(index));
+        });
+        assert_eq!(chain.generate_backwards_from_token(9), vec![]);
+    }
+
+    #[test]
+    fn generate_through_token() {
+        let mut chain = Chain::new();
+        chain.feed_str("I like cats").feed_str("you like dogs");
+        let sentences = ["I like cats", "I like dogs", "you like cats", "you like dogs"];
+        for _ in 0..10 {
+            assert!(sentences.contains(&&chain.generate_str_through_token("like")[..]));
+            assert!(sentences.contains(&&chain.generate_str_to_token("dogs")[..]));
         }
-        self
+        assert_eq!(chain.generate_str_through_token("birds"), "");
     }
 
-    /// Generates a collection of tokens from the chain. This operation is O(mn) where m is the
-    /// length of the generated collection, and n is the number of possible states from a given
-    /// state.
-    pub fn generate(&self) -> Vec<Rc<T>> {
-        let mut ret = Vec::new();
-        let mut curs = vec!(None; self.order);
-        loop {
-            let next = self.map[&curs].next();
-            curs = curs[1..self.order].to_vec();
-            curs.push(next.clone());
-            if let Some(next) = next { ret.push(next) };
-            if curs[self.order - 1].is_none() { break }
+    #[test]
+    fn generate_from_any() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5, 10]).feed(vec![7, 12]);
+        for _ in 0..10 {
+            let v: Vec<_> = chain.generate_from_any(&[(9, 5.0), (5, 1.0), (7, 0.0)])
+                                 .into_iter().map(|v| *v).collect();
+            assert_eq!(v, vec![5, 10]);
         }
-        ret
+        assert_eq!(chain.generate_from_any(&[(9, 1.0)]), vec![]);
     }
 
-    /// Generates a collection of tokens from the chain, starting with the given token. This
-    /// operation is O(mn) where m is the length of the generated collection, and n is the number
-    /// of possible states from a given state. This returns an empty vector if the token is not
-    /// found.
-    pub fn generate_from_token(&self, token: T) -> Vec<Rc<T>> {
-        let token = Rc::new(token);
-        if !self.map.contains_key(&vec!(Some(token.clone()); self.order)) { return Vec::new() }
-        let mut ret = vec![token.clone()];
-        let mut curs = vec!(Some(token.clone()); self.order);
-        loop {
-            let next = self.map[&curs].next();
-            curs = curs[1..self.order].to_vec();
-            curs.push(next.clone());
-            if let Some(next) = next { ret.push(next) };
-            if curs[self.order - 1].is_none() { break }
+    #[test]
+    fn generate_containing() {
+        let mut chain = Chain::new();
+        chain.feed(vec![1u8, 2]).feed(vec![1, 3]).feed(vec![1, 4]);
+        let v: Vec<_> = chain.generate_containing(&[3], 1000).unwrap()
+                             .into_iter().map(|v| *v).collect();
+        assert_eq!(v, vec![1, 3]);
+        assert_eq!(chain.generate_containing(&[2, 3], 100), None);
+        assert_eq!(chain.generate_containing(&[], 1).map(|v| v.len()), Some(2));
+    }
+
+    #[test]
+    fn generate_where() {
+        let mut chain = Chain::new();
+        chain.feed(vec![("hi", "alice"), ("there", "alice")])
+             .feed(vec![("hi", "bob"), ("there", "alice")]);
+        for _ in 0..10 {
+            let v: Vec<_> = chain.generate_where(|&(_, speaker)| speaker == "alice")
+                                 .into_iter().map(|v| *v).collect();
+            assert_eq!(v, vec![("hi", "alice"), ("there", "alice")]);
         }
-        ret
+        assert_eq!(chain.generate_where(|&(_, speaker)| speaker == "carol"), vec![]);
     }
 
-    /// Produces an infinite iterator of generated token collections.
-    pub fn iter(&self) -> InfiniteChainIterator<T> {
-        InfiniteChainIterator { chain: self }
+    #[test]
+    fn beam_search() {
+        let mut chain = Chain::new();
+        chain.feed_all(vec![vec![1u8], vec![1], vec![1], vec![1, 2, 3, 4], vec![1, 2, 3, 4]]);
+        let best = |options| -> Vec<Vec<u8>> {
+            chain.beam_search(options).into_iter()
+                 .map(|(tokens, _)| tokens.into_iter().map(|v| *v).collect()).collect()
+        };
+        let options = BeamOptions { length_penalty: 0.0, ..BeamOptions::default() };
+        assert_eq!(best(options), vec![vec![1], vec![1, 2, 3, 4]]);
+        assert_eq!(best(BeamOptions::default()), vec![vec![1, 2, 3, 4], vec![1]]);
+        assert_eq!(best(BeamOptions { max_len: 3, ..BeamOptions::default() }), vec![vec![1]]);
+        assert_eq!(best(BeamOptions { width: 1, ..BeamOptions::default() }),
+                   vec![vec![1, 2, 3, 4]]);
     }
 
-    /// Produces an iterator for the specified number of generated token collections.
-    pub fn iter_for(&self, size: usize) -> SizedChainIterator<T> {
-        SizedChainIterator { chain: self, size: size }
+    #[test]
+    fn generator() {
+        let mut chain = Chain::new();
+        chain.order(2);
+        chain.feed(vec![3u8, 5, 10, 12]);
+        let v: Vec<_> = chain.generator().map(|v| *v).collect();
+        assert_eq!(v, vec![3, 5, 10, 12]);
     }
-}
 
-impl Chain<String> {
-    /// Feeds a string of text into the chain.
-    pub fn feed_str(&mut self, string: &str) -> &mut Chain<String> {
-        self.feed(string.split(' ').map(|s| s.to_owned()).collect())
+    #[test]
+    fn generate_iter() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5, 10, 12]);
+        let mut iter = chain.generate_iter();
+        assert_eq!(iter.next().map(|v| *v), Some(3));
+        assert_eq!(iter.map(|v| *v).collect::<Vec<_>>(), vec![5, 10, 12]);
     }
 
-    /// Feeds a properly formatted file into the chain. This file should be formatted such that
-    /// each line is a new sentence. Punctuation may be included if it is desired.
-    pub fn feed_file<P: AsRef<Path>>(&mut self, path: P) -> &mut Chain<String> {
-        let reader = BufReader::new(File::open(path).unwrap());
-        for line in reader.lines() {
-            let line = line.unwrap();
-            let words = line.split_whitespace()
-                            .filter(|word| !word.is_empty())
-                            .map(|s| s.to_owned())
-                            .collect();
-            self.feed(words);
-        }
-        self
+    #[test]
+    fn stream() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5]);
+        let v: Vec<_> = chain.stream().take(5).map(|v| *v).collect();
+        assert_eq!(v, vec![3, 5, 3, 5, 3]);
+        let v: Vec<_> = chain.stream_with_boundary(0).take(5).map(|v| *v).collect();
+        assert_eq!(v, vec![3, 5, 0, 3, 5]);
+        assert_eq!(Chain::<u8>::new().stream().next(), None);
     }
 
-    /// Converts the output of generate(...) on a String chain to a single String.
-    fn vec_to_string(vec: Vec<Rc<String>>) -> String {
-        let mut ret = String::new();
-        for s in &vec {
-            ret.push_str(&s);
-            ret.push_str(" ");
+    #[test]
+    fn generate_exact() {
+        let mut chain = Chain::new();
+        chain.feed_str("I like cats");
+        assert_eq!(chain.generate_str_exact(5), "I like cats I like");
+        assert_eq!(chain.generate_exact(0), vec![]);
+        assert_eq!(Chain::<u8>::new().generate_exact(3), vec![]);
+    }
+
+    #[test]
+    fn continuous_iter() {
+        let mut chain = Chain::new();
+        chain.feed(vec![1u8, 2, 3, 2]);
+        for tokens in chain.iter_for(5).continuous().skip(1) {
+            assert_eq!(*tokens[0], 3);
         }
-        let len = ret.len();
-        if len > 0 {
-            ret.truncate(len - 1);
+        for tokens in chain.iter().continuous().skip(1).take(5) {
+            assert_eq!(*tokens[0], 3);
         }
-        ret
     }
 
-    /// Generates a random string of text.
-    pub fn generate_str(&self) -> String {
-        Chain::vec_to_string(self.generate())
+    #[test]
+    fn save_and_resume() {
+        let mut chain = Chain::new();
+        chain.order(2);
+        chain.feed(vec![3u8, 5, 10, 12]);
+        let mut walk = chain.generator();
+        walk.next();
+        walk.next();
+        let cursor = walk.save();
+        assert_eq!(cursor, vec![Some(3), Some(5)]);
+        let v: Vec<_> = chain.resume(cursor).unwrap().map(|v| *v).collect();
+        assert_eq!(v, vec![10, 12]);
+        assert!(chain.resume(vec![Some(5)]).is_none());
     }
 
-    /// Generates a random string of text starting with the desired token. This returns an empty
-    /// string if the token is not found.
-    pub fn generate_str_from_token(&self, string: &str) -> String {
-        Chain::vec_to_string(self.generate_from_token(string.to_owned()))
+    #[test]
+    fn iter() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5, 10]).feed(vec![5, 12]);
+        assert_eq!(chain.iter().size_hint().1, None);
     }
 
-    /// Produces an infinite iterator of generated strings.
-    pub fn str_iter(&self) -> InfiniteChainStringIterator {
-        let vec_to_string: fn(Vec<Rc<String>>) -> String = Chain::vec_to_string;
-        self.iter().map(vec_to_string)
+    #[test]
+    fn iter_for() {
+        let mut chain = Chain::new();
+        chain.feed(vec![3u8, 5, 10]).feed(vec![5, 12]);
+        assert_eq!(chain.iter_for(5).collect::<Vec<_>>().len(), 5);
     }
 
-    /// Produces a sized iterator of generated strings.
-    pub fn str_iter_for(&self, size: usize) -> SizedChainStringIterator {
-        let vec_to_string: fn(Vec<Rc<String>>) -> String = Chain::vec_to_string;
-        self.iter_for(size).map(vec_to_string)
+    #[test]
+    fn feed_str() {
+        let mut chain = Chain::new();
+        chain.feed_str("I like cats and dogs");
     }
-}
 
-/// A sized iterator over a Markov chain of strings.
-pub type SizedChainStringIterator<'a> =
-Map<SizedChainIterator<'a, String>, fn(Vec<Rc<String>>) -> String>;
+    #[test]
+    fn feed_str_unicode_whitespace() {
+        let mut chain = Chain::new();
+        chain.feed_str("I\tlike  cats\u{3000}\n");
+        assert_eq!(chain.generate_str(), "I like cats");
+    }
 
-/// A sized iterator over a Markov chain.
-pub struct SizedChainIterator<'a, T: Chainable + 'a> {
-    chain: &'a Chain<T>,
-    size: usize,
-}
+    #[test]
+    fn feed_str_with() {
+        let mut chain = Chain::new();
+        chain.feed_str_with("I like cats!", &PunctuationTokenizer);
+        assert_eq!(chain.generate_str(), "I like cats !");
+    }
 
-impl<'a, T> Iterator for SizedChainIterator<'a, T> where T: Chainable + 'a {
-    type Item = Vec<Rc<T>>;
-    fn next(&mut self) -> Option<Vec<Rc<T>>> {
-        if self.size > 0 {
-            self.size -= 1;
-            Some(self.chain.generate())
-        } else {
-            None
-        }
+    #[test]
+    fn feed_reader() {
+        let mut chain = Chain::new();
+        chain.feed_reader(&b"I like cats\nI like cats"[..]);
+        assert_eq!(chain.generate_str(), "I like cats");
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.size, Some(self.size))
+    #[test]
+    fn feed_dir() {
+        let dir = env::temp_dir().join(format!("markov-feed-dir-{}", process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.txt"), "I like cats").unwrap();
+        fs::write(dir.join("nested").join("b.txt"), "I like cats").unwrap();
+        fs::write(dir.join("nested").join("c.bin"), [0xffu8, 0xfe]).unwrap();
+        let mut chain = Chain::new();
+        let errors = chain.feed_dir(&dir);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, dir.join("nested").join("c.bin"));
+        assert_eq!(chain.generate_str(), "I like cats");
+        let mut chain = Chain::new();
+        let errors = chain.feed_dir_filtered(&dir, |p| p.extension() == Some("txt".as_ref()));
+        assert!(errors.is_empty());
+        assert_eq!(chain.generate_str(), "I like cats");
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Chain::new().feed_dir(&dir).len(), 1);
     }
-}
 
+    #[test]
+    fn feed_reader_with_progress() {
+        let mut chain = Chain::new();
+        let mut calls = 0;
+        let progress = chain.feed_reader_with_progress(&b"I like cats\r\nI like\n"[..], |p| {
+            calls += 1;
+            assert_eq!(p.lines, calls);
+        });
+        assert_eq!(calls, 2);
+        assert_eq!(progress, FeedProgress { bytes: 20, lines: 2, tokens: 5 });
+    }
 
-/// An infinite iterator over a Markov chain of strings.
-pub type InfiniteChainStringIterator<'a> =
-Map<InfiniteChainIterator<'a, String>, fn(Vec<Rc<String>>) -> String>;
+    #[test]
+    fn feed_file_with_progress() {
+        let mut chain = Chain::new();
+        let progress = chain.feed_file_with_progress("test", |_| ());
+        assert_eq!(progress.lines, 6);
+        assert!(!chain.is_empty());
+    }
 
-/// An infinite iterator over a Markov chain.
-pub struct InfiniteChainIterator<'a, T: Chainable + 'a> {
-    chain: &'a Chain<T>
-}
+    #[test]
+    fn csv() {
+        let mut chain = Chain::new();
+        chain.feed(vec!["I", "like"]);
+        let mut csv = Vec::new();
+        chain.to_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut rows: Vec<_> = csv.split_terminator("\r\n").collect();
+        rows[1..].sort();
+        assert_eq!(rows, vec!["context_1,next,count", ",I,1", "I,like,1", "like,,1"]);
+    }
 
-impl<'a, T> Iterator for InfiniteChainIterator<'a, T> where T: Chainable + 'a {
-    type Item = Vec<Rc<T>>;
-    fn next(&mut self) -> Option<Vec<Rc<T>>> {
-        Some(self.chain.generate())
+    #[test]
+    fn vocabulary() {
+        let mut chain = Chain::new();
+        chain.feed_str("I like cats").feed_str("I hate cats");
+        let vocabulary: Vec<_> = chain.vocabulary().map(|(token, n)| (&token[..], n)).collect();
+        assert_eq!(vocabulary, vec![("I", 2), ("like", 1), ("cats", 2), ("hate", 1)]);
+        let restricted = chain.restricted(|token| token != "hate" && token != "cats");
+        assert_eq!(restricted.vocab_len(), 2);
+        assert!(!restricted.contains_token(&"cats".to_owned()));
+        assert_eq!(restricted.probability(&["I".to_owned()], None), 0.5);
+        assert_eq!(restricted.probability(&["like".to_owned()], None), 1.0);
+        for _ in 0..10 {
+            assert!(["I", "I like"].contains(&&restricted.generate_str()[..]));
+        }
     }
-}
 
-/// A collection of states for the Markov chain.
-trait States<T: PartialEq> {
-    /// Adds a state to this states collection.
-    fn add(&mut self, token: Token<T>);
-    /// Gets the next state from this collection of states.
-    fn next(&self) -> Token<T>;
-}
+    #[test]
+    fn counts() {
+        let mut counts = HashMap::new();
+        counts.insert(vec![None], vec![(Some(1), 2)].into_iter().collect());
+        counts.insert(vec![Some(1)], vec![(Some(2), 1), (None, 1), (Some(3), 0)].into_iter()
+                                                                               .collect());
+        let chain: Chain<i32> = Chain::from_counts(counts.clone(), 1);
+        assert_eq!(chain.get_order(), 1);
+        assert!(!chain.contains_token(&3));
+        counts.get_mut(&vec![Some(1)]).unwrap().remove(&Some(3));
+        assert_eq!(chain.to_counts(), counts);
+    }
 
-impl<T> States<T> for HashMap<Token<T>, usize> where T: Chainable {
-    fn add(&mut self, token: Token<T>) {
-        match self.entry(token) {
-            Occupied(mut e) => *e.get_mut() += 1,
-            Vacant(e) => { e.insert(1); },
+    #[test]
+    fn feed_text() {
+        let mut chain = Chain::new();
+        chain.feed_text("I like cats. \"Do you?\" I do");
+        assert_eq!(chain.fed_sequences_count(), 3);
+        assert_eq!(chain.probability(&["cats.".to_owned()], None), 1.0);
+        assert_eq!(chain.probability(&["you?\"".to_owned()], None), 1.0);
+        let mut chain = Chain::new();
+        chain.feed_text_with("Hi! Bye", &PunctuationTokenizer);
+        assert_eq!(chain.length_distribution().keys().collect::<Vec<_>>(), vec![&1, &2]);
+    }
+
+    #[test]
+    fn feed_jsonl_and_csv() {
+        let jsonl = "{\"user\": \"a\", \"text\": \"I like cats\"}\n\n{\"user\": \"b\"}\n";
+        let mut chain = Chain::new();
+        chain.feed_jsonl(jsonl.as_bytes(), "text").unwrap();
+        assert_eq!(chain.generate_str(), "I like cats");
+        assert!(chain.feed_jsonl(&b"{\"text\": 1}"[..], "text").is_err());
+        let csv = "user,text\r\na,\"I like\r\ncats\"\r\nb\r\n";
+        let mut chain = Chain::new();
+        chain.feed_csv(csv.as_bytes(), "text").unwrap();
+        assert_eq!(chain.generate_str(), "I like cats");
+        assert!(chain.feed_csv(csv.as_bytes(), "missing").is_err());
+    }
+
+    #[test]
+    fn zero_counts() {
+        let mut chain: Chain<String> = Chain::load(&b"markov 1\n1\n0\tI\t\\-\n"[..]).unwrap();
+        assert_eq!(chain.validate(), Err(ValidationError::ZeroCount));
+        assert!(chain.generate().is_empty());
+        chain.feed_str("I like cats");
+        assert_eq!(chain.validate(), Ok(()));
+        let mut chain = Chain::new();
+        chain.feed(vec![1u8, 2]);
+        assert_eq!(chain.validate(), Ok(()));
+        chain.order(2);
+        assert_eq!(chain.validate(),
+                   Err(ValidationError::WrongStateLength { expected: 2, found: 1 }));
+    }
+
+    #[test]
+    fn validate() {
+        let mut chain = Chain::new();
+        chain.order(2).feed_str("I like cats").feed_tagged(vec!["I".to_owned()], "a");
+        assert_eq!(chain.validate(), Ok(()));
+        let saved = "markov 1\n1\n1\tI\t\\-\n1\t\\-\tI\n";
+        let loaded: Chain<String> = Chain::load(saved.as_bytes()).unwrap();
+        assert_eq!(loaded.validate(), Ok(()));
+        let loaded: Chain<String> = Chain::load(&saved.as_bytes()[..18]).unwrap();
+        assert_eq!(loaded.validate(), Err(ValidationError::DanglingTransition));
+        let mut counts = HashMap::new();
+        counts.insert(vec![Some(1u8)], vec![(None, 1)].into_iter().collect());
+        let mut chain: Chain<u8> = Chain::from_counts(counts, 1);
+        chain.map.remove(&vec![None]);
+        assert_eq!(chain.validate(), Err(ValidationError::MissingStartState));
+    }
+
+    #[test]
+    fn saturating_counts() {
+        let mut counts = HashMap::new();
+        counts.insert(vec![None], vec![(Some(1), usize::MAX)].into_iter().collect());
+        counts.insert(vec![Some(1)], vec![(None, usize::MAX)].into_iter().collect());
+        let mut chain: Chain<u8> = Chain::from_counts(counts.clone(), 1);
+        chain.feed(vec![1, 2]).feed(vec![1]);
+        assert_eq!(chain.to_counts()[&vec![None]][&Some(1)], usize::MAX);
+        assert!(chain.probability(&[1], Some(&2)) < 1e-9);
+        for _ in 0..10 {
+            assert!([vec![Rc::new(1)], vec![Rc::new(1), Rc::new(2)]].contains(&chain.generate()));
         }
     }
 
-    fn next(&self) -> Token<T> {
-        let mut sum = 0;
-        for &value in self.values() {
-            sum += value;
+    #[test]
+    fn saturating_totals() {
+        let mut counts = HashMap::new();
+        counts.insert(vec![None], vec![(Some(1), usize::MAX)].into_iter().collect());
+        counts.insert(vec![Some(1)], vec![(None, usize::MAX)].into_iter().collect());
+        let mut chain: Chain<u8> = Chain::from_counts(counts, 1);
+        chain.feed(vec![1, 2]);
+        for _ in 0..10 {
+            let traced = chain.generate_traced();
+            assert!(traced.iter().all(|&(_, p)| p > 0.0 && p <= 1.0));
         }
-        let mut rng = thread_rng();
-        let cap = rng.gen_range(0, sum);
-        sum = 0;
-        for (key, &value) in self.iter() {
-            sum += value;
-            if sum > cap {
-                return key.clone()
-            }
+        chain.smoothing(1.0);
+        for _ in 0..10 {
+            assert!([vec![Rc::new(1)], vec![Rc::new(1), Rc::new(2)]].contains(&chain.generate()));
+            assert!(chain.generate_traced().iter().all(|&(_, p)| p > 0.0 && p <= 1.0));
         }
-        unreachable!("The random number generator failed.")
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::Chain;
 
     #[test]
-    fn new() {
-        Chain::<u8>::new();
-        Chain::<String>::new();
+    fn max_count() {
+        let mut chain = Chain::new();
+        chain.feed_str("lol").feed_str("lol").feed_str("lol").feed_str("ok");
+        chain.max_count(2);
+        assert_eq!(chain.probability(&[], Some(&"lol".to_owned())), 2.0 / 3.0);
+        chain.feed_str("lol").feed_str("ok");
+        assert_eq!(chain.probability(&[], Some(&"ok".to_owned())), 0.5);
     }
 
     #[test]
-    fn is_empty() {
+    fn deduplicate() {
         let mut chain = Chain::new();
-        assert!(chain.is_empty());
-        chain.feed(vec![1u8, 2, 3]);
-        assert!(!chain.is_empty());
+        chain.deduplicate(true).feed_str("lol").feed_str("lol");
+        chain.feed_tagged(vec!["lol".to_owned()], "a");
+        chain.feed_str("I lol");
+        assert_eq!(chain.fed_sequences_count(), 2);
+        assert_eq!(chain.tags().count(), 0);
+        chain.deduplicate(false).feed_str("lol");
+        assert_eq!(chain.fed_sequences_count(), 3);
     }
 
     #[test]
-    fn feed() {
+    fn feed_filtered() {
         let mut chain = Chain::new();
-        chain.feed(vec![3, 5, 10]).feed(vec![5, 12]);
+        chain.feed_filtered(vec![1u8, 0, 2, 0], |&token| token != 0);
+        assert_eq!(chain.generate(), vec![Rc::new(1), Rc::new(2)]);
+        let tokenizer = FilteredTokenizer(WhitespaceTokenizer, |token: &str| token != "the");
+        let mut chain = Chain::new();
+        chain.feed_reader_with(&b"the cat\nthe dog\n"[..], &tokenizer);
+        assert_eq!(chain.vocab_len(), 2);
     }
 
     #[test]
-    fn generate() {
+    fn feed_reader_with_checkpoints() {
         let mut chain = Chain::new();
-        chain.feed(vec![3u8, 5, 10]).feed(vec![5, 12]);
-        let v = chain.generate().into_iter().map(|v| *v).collect();
-        assert!([vec![3, 5, 10], vec![3, 5, 12], vec![5, 10], vec![5, 12]].contains(&v));
+        let mut saved = Vec::new();
+        let progress = chain.feed_reader_with_checkpoints(&b"a b\nc d\ne f\n"[..], 2, |chain, p| {
+            saved.clear();
+            chain.save(&mut saved)?;
+            assert_eq!(p.lines, 2);
+            Ok(())
+        }).unwrap();
+        assert_eq!(progress.lines, 3);
+        let mut resumed: Chain<String> = Chain::load(&saved[..]).unwrap();
+        resumed.feed_reader(&b"e f\n"[..]);
+        assert_eq!(resumed.sorted_edges(), chain.sorted_edges());
+        let failing = chain.feed_reader_with_checkpoints(&b"g\nh\n"[..], 1, |_, _| {
+            Err(io::Error::other("disk full"))
+        });
+        assert_eq!(failing.unwrap_err().to_string(), "disk full");
+        assert!(!chain.contains_token(&"h".to_owned()));
     }
 
     #[test]
-    fn generate_for_higher_order() {
+    fn generate_str() {
         let mut chain = Chain::new();
-        chain.order(2);
-        chain.feed(vec![3u8, 5, 10]).feed(vec![2, 3, 5, 12]);
-        let v = chain.generate().into_iter().map(|v| *v).collect();
-        assert!([vec![3, 5, 10], vec![3, 5, 12], vec![2, 3, 5, 10], vec![2, 3, 5, 12]].contains(&v));
+        chain.feed_str("I like cats").feed_str("I hate cats");
+        assert!(["I like cats", "I hate cats"].contains(&&chain.generate_str()[..]));
     }
 
     #[test]
-    fn generate_from_token() {
+    fn generate_str_multibyte() {
         let mut chain = Chain::new();
-        chain.feed(vec![3u8, 5, 10]).feed(vec![5, 12]);
-        let v = chain.generate_from_token(5).into_iter().map(|v| *v).collect();
-        assert!([vec![5, 10], vec![5, 12]].contains(&v));
+        chain.feed_str("こんにちは 世界");
+        assert_eq!(chain.generate_str(), "こんにちは 世界");
     }
 
     #[test]
-    fn generate_from_unfound_token() {
+    fn generate_str_with() {
         let mut chain = Chain::new();
-        chain.feed(vec![3u8, 5, 10]).feed(vec![5, 12]);
-        let v: Vec<_> = chain.generate_from_token(9).into_iter().map(|v| *v).collect();
-        assert_eq!(v, vec![]);
+        chain.feed_str_with("I like cats!", &PunctuationTokenizer);
+        assert_eq!(chain.generate_str_with(&PunctuationDetokenizer), "I like cats!");
     }
 
     #[test]
-    fn iter() {
+    fn generate_str_with_spans() {
+        let spans = SpanTokenizer::new();
         let mut chain = Chain::new();
-        chain.feed(vec![3u8, 5, 10]).feed(vec![5, 12]);
-        assert_eq!(chain.iter().size_hint().1, None);
+        chain.feed_str_with("It ends.  Then\tit goes on.", &spans);
+        assert_eq!(chain.generate_str_with(&spans), "It ends.  Then\tit goes on.");
     }
 
     #[test]
-    fn iter_for() {
+    fn generate_paragraph() {
         let mut chain = Chain::new();
-        chain.feed(vec![3u8, 5, 10]).feed(vec![5, 12]);
-        assert_eq!(chain.iter_for(5).collect::<Vec<_>>().len(), 5);
+        chain.feed_str("i like cats");
+        assert_eq!(chain.generate_paragraph(2), "I like cats. I like cats.");
+        assert_eq!(Chain::new().generate_paragraph(2), "");
     }
 
     #[test]
-    fn feed_str() {
+    fn generate_linked_paragraph() {
         let mut chain = Chain::new();
-        chain.feed_str("I like cats and dogs");
+        chain.feed_str("cats purr. dogs bark! cats purr.");
+        for _ in 0..10 {
+            let tail = [Rc::new("cats".to_owned()), Rc::new("purr.".to_owned())];
+            let v = chain.generate_after(&tail);
+            assert_eq!(v[..2], [Rc::new("dogs".to_owned()), Rc::new("bark!".to_owned())]);
+            let paragraph = chain.generate_linked_paragraph(2);
+            assert!(paragraph.starts_with("Cats purr. ") && paragraph.contains("Dogs bark!"));
+        }
     }
 
     #[test]
-    fn generate_str() {
+    fn generate_str_containing() {
         let mut chain = Chain::new();
-        chain.feed_str("I like cats").feed_str("I hate cats");
-        assert!(["I like cats", "I hate cats"].contains(&&chain.generate_str()[..]));
+        chain.feed_str("I like cats").feed_str("I like dogs");
+        assert_eq!(chain.generate_str_containing(&["dogs"], 1000).unwrap(), "I like dogs");
+        assert_eq!(chain.generate_str_containing(&["birds"], 10), None);
     }
 
     #[test]
@@ -377,6 +3586,13 @@ mod test {
         assert_eq!(chain.generate_str_from_token("test"), "");
     }
 
+    #[test]
+    fn generate_str_from_any() {
+        let mut chain = Chain::new();
+        chain.feed_str("I like cats").feed_str("cats are cute");
+        assert_eq!(chain.generate_str_from_any(&[("test", 1.0), ("are", 1.0)]), "are cute");
+    }
+
     #[test]
     fn str_iter() {
         let mut chain = Chain::new();
@@ -390,4 +3606,21 @@ mod test {
         chain.feed_str("I like cats and I like dogs");
         assert_eq!(chain.str_iter_for(5).collect::<Vec<_>>().len(), 5);
     }
+
+    #[test]
+    fn str_iter_with() {
+        let mut chain = Chain::new();
+        chain.feed_str("usr bin");
+        let detokenizer = SeparatorDetokenizer("/");
+        assert_eq!(chain.str_iter_with(&detokenizer).next().unwrap(), "usr/bin");
+    }
+
+    #[test]
+    fn str_iter_for_with() {
+        let mut chain = Chain::new();
+        chain.feed_str("usr bin");
+        let detokenizer = SeparatorDetokenizer("");
+        assert_eq!(chain.str_iter_for_with(2, &detokenizer).collect::<Vec<_>>(),
+                   vec!["usrbin", "usrbin"]);
+    }
 }
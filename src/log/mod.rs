@@ -0,0 +1,129 @@
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::hash::BuildHasher;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use {Chainable, GenericChain, SharedPtr};
+use format;
+
+/// An append-only log of the collections fed into a chain, from which the chain can be rebuilt
+/// with `replay_log` after a crash, without saving the whole chain after every collection. Each
+/// collection is written to the file before it is fed into the chain, with one collection per
+/// line and tokens written as by `save`.
+///
+/// Together with `save`, this allows cheap checkpoints: save the chain now and then, noting the
+/// number of entries in the log at the time, and after a crash load the last save and replay
+/// the log from that entry onwards.
+#[derive(Debug)]
+pub struct TrainingLog {
+    file: File,
+    entries: usize,
+}
+
+impl TrainingLog {
+    /// Opens the log at the given path for appending, creating it if it does not exist. An
+    /// incomplete entry at the end of the file, as left by a crash while writing it, is removed.
+    pub fn open<Q: AsRef<Path>>(path: Q) -> io::Result<TrainingLog> {
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let (entries, complete) = count_entries(BufReader::new(&mut file))?;
+        file.set_len(complete)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(TrainingLog { file, entries })
+    }
+
+    /// Gets the number of collections recorded in the log.
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    /// Records a collection of tokens in the log, and then feeds it into the chain. The entry is
+    /// handed to the operating system before the chain is changed, so it survives the process
+    /// crashing; use `sync` to also make it survive the machine crashing. An empty collection is
+    /// neither recorded nor fed, as it would leave the chain unchanged.
+    pub fn feed<T, P, H>(&mut self, chain: &mut GenericChain<T, P, H>, tokens: Vec<T>)
+        -> io::Result<()>
+        where T: Chainable + Display, P: SharedPtr<T>, H: BuildHasher + Default {
+        if tokens.is_empty() { return Ok(()) }
+        let mut line = tokens.iter().map(|token| format::escape(Some(token)))
+                             .collect::<Vec<_>>().join("\t");
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.entries += 1;
+        chain.feed(tokens);
+        Ok(())
+    }
+
+    /// Waits until every entry recorded so far has been written to disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+/// Counts the complete entries of a log, returning their number and their length in bytes.
+fn count_entries<R: BufRead>(mut reader: R) -> io::Result<(usize, u64)> {
+    let (mut entries, mut complete) = (0, 0);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 || line.last() != Some(&b'\n') { return Ok((entries, complete)) }
+        entries += 1;
+        complete += read as u64;
+    }
+}
+
+/// Reads the complete entries of a log written by `TrainingLog`, skipping the first `skip` of
+/// them, and passes each one on as a collection of tokens. This returns the number of complete
+/// entries in the log.
+pub(crate) fn replay<R, T, F>(mut reader: R, skip: usize, mut feed: F) -> io::Result<usize>
+    where R: BufRead, T: FromStr, F: FnMut(Vec<T>) {
+    let mut entries = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        if line.pop() != Some('\n') { return Ok(entries) }
+        entries += 1;
+        if entries <= skip { continue }
+        let tokens = line.split('\t').map(|field| format::unescape(field)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Found the end of a collection in a log.")
+        })).collect::<io::Result<Vec<_>>>()?;
+        feed(tokens);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use Chain;
+    use super::TrainingLog;
+
+    #[test]
+    fn replay_after_crash() {
+        let path = env::temp_dir().join(format!("markov-training-log-{}.log", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut chain = Chain::new();
+        let mut log = TrainingLog::open(&path).unwrap();
+        log.feed(&mut chain, vec!["I".to_owned(), "like\tcats".to_owned()]).unwrap();
+        log.feed(&mut chain, Vec::new()).unwrap();
+        log.feed(&mut chain, vec!["dogs".to_owned()]).unwrap();
+        assert_eq!(log.entries(), 2);
+        drop(log);
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"torn").unwrap();
+        let mut replayed = Chain::new();
+        assert_eq!(replayed.replay_log(&path, 0).unwrap(), 2);
+        assert_eq!(replayed, chain);
+        let mut log = TrainingLog::open(&path).unwrap();
+        assert_eq!(log.entries(), 2);
+        let mut caught_up = Chain::new();
+        caught_up.feed(vec!["I".to_owned(), "like\tcats".to_owned()]);
+        log.feed(&mut chain, vec!["birds".to_owned()]).unwrap();
+        assert_eq!(caught_up.replay_log(&path, 1).unwrap(), 3);
+        assert_eq!(caught_up, chain);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! Cleaners that strip markup from scraped text line by line as it is streamed into a chain of
+//! strings, so that tags and code do not become tokens. This is only available with the `markup`
+//! feature enabled.
+//!
+//! ```
+//! use markov::Chain;
+//! use markov::markup::HtmlCleaner;
+//!
+//! let mut chain = Chain::new();
+//! chain.feed_reader_cleaned(&b"<p>I like <b>cats</b> &amp; dogs</p>\n"[..], HtmlCleaner::new());
+//! assert_eq!(chain.generate_str(), "I like cats & dogs");
+//! ```
+
+/// A strategy for cleaning a line of text before it is fed into a chain. Cleaners see every line
+/// in order, so they may keep state across lines, e.g. to skip a block spanning several lines.
+pub trait Cleaner {
+    /// Cleans a line, returning the text to feed. Returning an empty or whitespace-only string
+    /// skips the line.
+    fn clean(&mut self, line: &str) -> String;
+}
+
+impl<F> Cleaner for F where F: FnMut(&str) -> String {
+    fn clean(&mut self, line: &str) -> String {
+        self(line)
+    }
+}
+
+/// Strips HTML tags and comments, which may span lines, and decodes the common character
+/// entities. The contents of `script` and `style` elements are skipped entirely.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HtmlCleaner {
+    /// The text of the tag being read, if a tag was opened and not yet closed.
+    tag: Option<String>,
+    /// The name of the element whose contents are being skipped.
+    skipping: Option<&'static str>,
+}
+
+impl HtmlCleaner {
+    /// Constructs a cleaner at the start of a document.
+    pub fn new() -> HtmlCleaner {
+        HtmlCleaner::default()
+    }
+
+    /// Updates the skipped element given the text of a finished tag.
+    fn close_tag(&mut self, tag: &str) {
+        let name: String = tag.trim_start_matches('/')
+                              .chars().take_while(|c| c.is_ascii_alphanumeric())
+                              .collect::<String>().to_ascii_lowercase();
+        let element = ["script", "style"].iter().find(|&&element| element == name);
+        match (self.skipping, element) {
+            (None, Some(&element)) if !tag.starts_with('/') && !tag.ends_with('/') => {
+                self.skipping = Some(element)
+            },
+            (Some(skipped), Some(&element)) if tag.starts_with('/') && skipped == element => {
+                self.skipping = None
+            },
+            _ => (),
+        }
+    }
+}
+
+impl Cleaner for HtmlCleaner {
+    fn clean(&mut self, line: &str) -> String {
+        let mut text = String::new();
+        for c in line.chars() {
+            match self.tag.take() {
+                // Only closing tags are read within skipped elements, so scripts may contain `<`.
+                Some(ref tag) if self.skipping.is_some() && tag.is_empty() && c != '/' => (),
+                Some(mut tag) => {
+                    let comment = tag.starts_with("!--");
+                    if c == '>' && (!comment || tag.len() >= 5 && tag.ends_with("--")) {
+                        self.close_tag(&tag);
+                        text.push(' ');
+                    } else {
+                        tag.push(c);
+                        self.tag = Some(tag);
+                    }
+                },
+                None if c == '<' => self.tag = Some(String::new()),
+                None if self.skipping.is_none() => text.push(c),
+                None => (),
+            }
+        }
+        decode_entities(&text)
+    }
+}
+
+/// Decodes the character entities most common in scraped text, along with numeric entities.
+/// Unknown entities are left as they are.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| &rest[1..end]);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X"))
+                       .map_or_else(|| entity.strip_prefix('#').and_then(|n| n.parse().ok()),
+                                    |n| u32::from_str_radix(n, 16).ok())
+                       .and_then(char::from_u32),
+        });
+        match (entity, c) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            },
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Skips fenced code blocks in Markdown, delimited by lines starting with at least three
+/// backticks or tildes. Other lines are passed through unchanged.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct MarkdownCleaner {
+    /// The fence that opened the code block being skipped, if any.
+    fence: Option<String>,
+}
+
+impl MarkdownCleaner {
+    /// Constructs a cleaner at the start of a document.
+    pub fn new() -> MarkdownCleaner {
+        MarkdownCleaner::default()
+    }
+}
+
+impl Cleaner for MarkdownCleaner {
+    fn clean(&mut self, line: &str) -> String {
+        let trimmed = line.trim_start();
+        let marker = trimmed.chars().next().filter(|&c| c == '`' || c == '~');
+        let run: String = marker.map_or_else(String::new, |marker| {
+            trimmed.chars().take_while(|&c| c == marker).collect()
+        });
+        match self.fence.take() {
+            Some(fence) => {
+                let closes = run.starts_with(&fence[..]) && trimmed[run.len()..].trim().is_empty();
+                if !closes { self.fence = Some(fence) }
+                String::new()
+            },
+            None if run.len() >= 3 => {
+                self.fence = Some(run);
+                String::new()
+            },
+            None => line.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cleaner, HtmlCleaner, MarkdownCleaner, decode_entities};
+
+    #[test]
+    fn html() {
+        let mut cleaner = HtmlCleaner::new();
+        assert_eq!(cleaner.clean("a<br>b <!-- x > y -->c <a"), "a b  c ");
+        assert_eq!(cleaner.clean("href='x'>d</a><script>"), " d  ");
+        assert_eq!(cleaner.clean("var x = 1 < 2;"), "");
+        assert_eq!(cleaner.clean("</SCRIPT>e"), " e");
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(decode_entities("&lt;a&gt; &#65;&#x42; &bogus; & x;"), "<a> AB &bogus; & x;");
+    }
+
+    #[test]
+    fn markdown() {
+        let mut cleaner = MarkdownCleaner::new();
+        let lines = ["Some text", "````rust", "let x = 1;", "```", "still code", "````", "More"];
+        let cleaned: Vec<_> = lines.iter().map(|line| cleaner.clean(line)).collect();
+        assert_eq!(cleaned, vec!["Some text", "", "", "", "", "", "More"]);
+    }
+}
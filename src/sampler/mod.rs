@@ -0,0 +1,92 @@
+//! Strategies for choosing the next token during generation.
+//!
+//! # Examples
+//!
+//! ```
+//! use markov::Chain;
+//! use markov::sampler::GreedySampler;
+//!
+//! let mut chain = Chain::new();
+//! chain.feed(vec![1u8, 2]).feed(vec![1u8, 2]).feed(vec![1u8, 3]);
+//! assert_eq!(chain.generate_with_sampler(&mut GreedySampler), vec![1.into(), 2.into()]);
+//! ```
+
+use std::cmp::Reverse;
+use rand::Rng;
+
+use choose_weighted;
+
+/// A strategy for choosing the next token from the continuations of the current state.
+pub trait Sampler<T> {
+    /// Chooses one of the candidates, returning its index. Each candidate is a token, or `None`
+    /// for the end of the sequence, along with the number of times it was observed. There is
+    /// always at least one candidate, and every count is positive.
+    fn choose<R: Rng>(&mut self, candidates: &[(Option<&T>, usize)], rng: &mut R) -> usize;
+}
+
+/// Chooses candidates in proportion to how often they were observed. This is the strategy used by
+/// `generate`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct WeightedSampler;
+
+impl<T> Sampler<T> for WeightedSampler {
+    fn choose<R: Rng>(&mut self, candidates: &[(Option<&T>, usize)], rng: &mut R) -> usize {
+        choose_weighted(candidates.iter().map(|&(_, count)| count as u64), rng)
+            .expect("Found no candidates with positive counts.")
+    }
+}
+
+/// Chooses among only the given number of most frequently observed candidates, in proportion to
+/// how often they were observed. A limit of zero is treated as one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TopKSampler(pub usize);
+
+impl<T> Sampler<T> for TopKSampler {
+    fn choose<R: Rng>(&mut self, candidates: &[(Option<&T>, usize)], rng: &mut R) -> usize {
+        let mut indices: Vec<_> = (0..candidates.len()).collect();
+        indices.sort_by_key(|&i| Reverse(candidates[i].1));
+        indices.truncate(self.0.max(1));
+        let top: Vec<_> = indices.iter().map(|&i| candidates[i]).collect();
+        indices[WeightedSampler.choose(&top, rng)]
+    }
+}
+
+/// Always chooses the most frequently observed candidate, breaking ties arbitrarily.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct GreedySampler;
+
+impl<T> Sampler<T> for GreedySampler {
+    fn choose<R: Rng>(&mut self, candidates: &[(Option<&T>, usize)], _: &mut R) -> usize {
+        (0..candidates.len()).max_by_key(|&i| candidates[i].1).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::thread_rng;
+    use super::{GreedySampler, Sampler, TopKSampler, WeightedSampler};
+
+    #[test]
+    fn weighted() {
+        let candidates = [(Some(&1), 1), (None, 3)];
+        let chosen = (0..1000).filter(|_| {
+            WeightedSampler.choose(&candidates, &mut thread_rng()) == 1
+        }).count();
+        assert!(chosen > 650 && chosen < 850);
+    }
+
+    #[test]
+    fn top_k() {
+        let candidates = [(Some(&1), 1), (Some(&2), 5), (None, 3)];
+        for _ in 0..10 {
+            assert_eq!(TopKSampler(1).choose(&candidates, &mut thread_rng()), 1);
+            assert_ne!(TopKSampler(2).choose(&candidates, &mut thread_rng()), 0);
+        }
+    }
+
+    #[test]
+    fn greedy() {
+        let candidates = [(Some(&1), 1), (None, 3), (Some(&2), 2)];
+        assert_eq!(GreedySampler.choose(&candidates, &mut thread_rng()), 1);
+    }
+}
@@ -0,0 +1,135 @@
+//! Utilities for testing code that uses Markov chains. These are only available with the
+//! `test_util` feature enabled.
+//!
+//! # Examples
+//!
+//! ```
+//! use markov::test_util::{assert_distribution, chain_from_lines};
+//!
+//! let chain = chain_from_lines(&["I like cats", "I like dogs"], 1);
+//! assert_distribution(1000, || chain.generate_str(),
+//!                     &[("I like cats".to_owned(), 0.5), ("I like dogs".to_owned(), 0.5)], 0.1);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use rand::Rng;
+
+use {ArcChain, Chain};
+
+/// Constructs a chain of the given order fed with each of the lines as a sentence.
+pub fn chain_from_lines(lines: &[&str], order: usize) -> Chain<String> {
+    let mut chain = Chain::new();
+    chain.order(order);
+    for line in lines {
+        chain.feed_str(line);
+    }
+    chain
+}
+
+/// Constructs an `ArcChain` of the given order fed with each of the lines as a sentence.
+pub fn arc_chain_from_lines(lines: &[&str], order: usize) -> ArcChain<String> {
+    let mut chain = ArcChain::new();
+    chain.order(order);
+    for line in lines {
+        chain.feed_str(line);
+    }
+    chain
+}
+
+/// A random number generator that produces a scripted sequence of values, repeating it once it
+/// is exhausted. Note that the chain's internal maps are unordered, so a scripted generator makes
+/// generation repeatable within a chain but not across separately constructed chains unless each
+/// state has a single continuation.
+#[derive(Clone, Debug)]
+pub struct FixedRng {
+    values: Vec<u32>,
+    index: usize,
+}
+
+impl FixedRng {
+    /// Constructs a generator producing the given values in order. This panics if no values are
+    /// given.
+    pub fn new(values: Vec<u32>) -> FixedRng {
+        assert!(!values.is_empty(), "FixedRng requires at least one value.");
+        FixedRng { values, index: 0 }
+    }
+}
+
+impl Rng for FixedRng {
+    fn next_u32(&mut self) -> u32 {
+        let value = self.values[self.index];
+        self.index = (self.index + 1) % self.values.len();
+        value
+    }
+}
+
+/// Draws the given number of samples and returns the observed relative frequency of each
+/// distinct outcome.
+pub fn sample_distribution<K, F>(samples: usize, mut sample: F) -> HashMap<K, f64>
+    where K: Eq + Hash, F: FnMut() -> K {
+    let mut counts = HashMap::new();
+    for _ in 0..samples {
+        *counts.entry(sample()).or_insert(0usize) += 1;
+    }
+    counts.into_iter().map(|(key, count)| (key, count as f64 / samples as f64)).collect()
+}
+
+/// Draws the given number of samples and asserts that the observed frequency of every outcome is
+/// within `tolerance` of its expected probability. Outcomes missing from `expected` are expected
+/// never to occur. This panics with a description of the offending outcome otherwise.
+pub fn assert_distribution<K, F>(samples: usize, sample: F, expected: &[(K, f64)], tolerance: f64)
+    where K: Eq + Hash + Debug, F: FnMut() -> K {
+    let observed = sample_distribution(samples, sample);
+    for &(ref key, probability) in expected {
+        let actual = observed.get(key).cloned().unwrap_or(0.0);
+        assert!((actual - probability).abs() <= tolerance,
+                "Expected {:?} with probability {}, observed {}.", key, probability, actual);
+    }
+    for (key, &actual) in &observed {
+        assert!(expected.iter().any(|(k, _)| k == key),
+                "Unexpected outcome {:?}, observed with probability {}.", key, actual);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+    use super::{FixedRng, arc_chain_from_lines, assert_distribution, chain_from_lines};
+
+    #[test]
+    fn from_lines() {
+        assert_eq!(chain_from_lines(&["I like cats"], 2).generate_str(), "I like cats");
+        assert_eq!(arc_chain_from_lines(&["I like cats"], 2).generate_str(), "I like cats");
+    }
+
+    #[test]
+    fn fixed_rng() {
+        let mut rng = FixedRng::new(vec![1, 2]);
+        assert_eq!((rng.next_u32(), rng.next_u32(), rng.next_u32()), (1, 2, 1));
+    }
+
+    #[test]
+    fn fixed_rng_generation() {
+        let chain = chain_from_lines(&["a b", "a c"], 1);
+        let first = chain.generate_with_rng(&mut FixedRng::new(vec![0]));
+        for _ in 0..10 {
+            assert_eq!(chain.generate_with_rng(&mut FixedRng::new(vec![0])), first);
+        }
+    }
+
+    #[test]
+    fn distribution() {
+        let chain = chain_from_lines(&["a b", "a b", "a c"], 1);
+        assert_distribution(3000, || chain.generate_str(),
+                            &[("a b".to_owned(), 2.0 / 3.0), ("a c".to_owned(), 1.0 / 3.0)], 0.05);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected outcome")]
+    fn distribution_unexpected() {
+        let chain = chain_from_lines(&["a b", "a c"], 1);
+        assert_distribution(100, || chain.generate_str(), &[("a b".to_owned(), 1.0)], 1.0);
+    }
+}
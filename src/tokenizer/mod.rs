@@ -0,0 +1,335 @@
+//! Tokenizers for splitting strings into the tokens fed into a chain of strings, and
+//! detokenizers for joining generated tokens back into strings.
+
+use std::borrow::ToOwned;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use rand::{Rng, thread_rng};
+
+/// A strategy for splitting a string of text into tokens.
+pub trait Tokenizer {
+    /// Splits the string into a sequence of tokens.
+    fn tokenize(&self, string: &str) -> Vec<String>;
+}
+
+impl<F> Tokenizer for F where F: Fn(&str) -> Vec<String> {
+    fn tokenize(&self, string: &str) -> Vec<String> {
+        self(string)
+    }
+}
+
+/// Splits text on any Unicode whitespace. This is the tokenizer used by `feed_str` and
+/// `feed_file`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, string: &str) -> Vec<String> {
+        string.split_whitespace().map(|s| s.to_owned()).collect()
+    }
+}
+
+/// Splits text on whitespace and additionally separates every punctuation character into its
+/// own token, so that `"Hello, world!"` becomes `["Hello", ",", "world", "!"]`. Apostrophes and
+/// hyphens surrounded by letters or digits are kept as part of the word, so contractions such as
+/// `don't` and compounds such as `well-known` stay intact.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PunctuationTokenizer;
+
+impl Tokenizer for PunctuationTokenizer {
+    fn tokenize(&self, string: &str) -> Vec<String> {
+        let mut ret = Vec::new();
+        for word in string.split_whitespace() {
+            let chars: Vec<char> = word.chars().collect();
+            let mut current = String::new();
+            for (i, &c) in chars.iter().enumerate() {
+                let joiner = (c == '\'' || c == '’' || c == '-') && i > 0 && i + 1 < chars.len() &&
+                             chars[i - 1].is_alphanumeric() && chars[i + 1].is_alphanumeric();
+                if c.is_alphanumeric() || joiner {
+                    current.push(c);
+                } else {
+                    if !current.is_empty() {
+                        ret.push(current);
+                        current = String::new();
+                    }
+                    ret.push(c.to_string());
+                }
+            }
+            if !current.is_empty() {
+                ret.push(current);
+            }
+        }
+        ret
+    }
+}
+
+/// Wraps another tokenizer and lowercases every token it produces, so that differently
+/// capitalized forms of a word share a single state.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct LowercaseTokenizer<K>(pub K);
+
+impl<K> Tokenizer for LowercaseTokenizer<K> where K: Tokenizer {
+    fn tokenize(&self, string: &str) -> Vec<String> {
+        self.0.tokenize(string).into_iter().map(|s| s.to_lowercase()).collect()
+    }
+}
+
+/// Wraps another tokenizer and keeps only the tokens for which the predicate returns true, e.g.
+/// to drop URLs, mentions or stop words while feeding a chain.
+///
+/// ```
+/// use markov::Chain;
+/// use markov::tokenizer::{FilteredTokenizer, WhitespaceTokenizer};
+///
+/// let tokenizer = FilteredTokenizer(WhitespaceTokenizer, |token: &str| !token.starts_with('@'));
+/// let mut chain = Chain::new();
+/// chain.feed_str_with("@bob I like cats", &tokenizer);
+/// assert_eq!(chain.generate_str(), "I like cats");
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FilteredTokenizer<K, F>(pub K, pub F);
+
+impl<K, F> Tokenizer for FilteredTokenizer<K, F> where K: Tokenizer, F: Fn(&str) -> bool {
+    fn tokenize(&self, string: &str) -> Vec<String> {
+        self.0.tokenize(string).into_iter().filter(|s| (self.1)(s)).collect()
+    }
+}
+
+/// Splits text on whitespace like `WhitespaceTokenizer`, additionally recording the exact
+/// whitespace that followed each token, such as double spaces after a period or a newline after a
+/// heading. Used as a `Detokenizer`, it joins each token to the next with one of the spans
+/// observed after that token, chosen in proportion to how often it was observed, so generated text
+/// reproduces the spacing of the corpus. Tokens never observed to be followed by another are
+/// joined with a single space. The same tokenizer should therefore be used to feed the chain and
+/// to generate from it.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SpanTokenizer {
+    spans: RefCell<HashMap<String, HashMap<String, usize>>>,
+}
+
+impl SpanTokenizer {
+    /// Constructs a tokenizer that has not yet recorded any spans.
+    pub fn new() -> SpanTokenizer {
+        SpanTokenizer::default()
+    }
+}
+
+impl Tokenizer for SpanTokenizer {
+    fn tokenize(&self, string: &str) -> Vec<String> {
+        let mut ret: Vec<String> = Vec::new();
+        let mut spans = self.spans.borrow_mut();
+        let mut rest = string.trim_start();
+        while !rest.is_empty() {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let (token, tail) = rest.split_at(end);
+            let next = tail.trim_start();
+            if !next.is_empty() {
+                let span = &tail[..tail.len() - next.len()];
+                let counts = spans.entry(token.to_owned()).or_default();
+                *counts.entry(span.to_owned()).or_insert(0) += 1;
+            }
+            ret.push(token.to_owned());
+            rest = next;
+        }
+        ret
+    }
+}
+
+impl Detokenizer for SpanTokenizer {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        let spans = self.spans.borrow();
+        let mut rng = thread_rng();
+        let mut ret = String::new();
+        for (i, &token) in tokens.iter().enumerate() {
+            if i > 0 {
+                let span = spans.get(tokens[i - 1]).map(|spans| {
+                    let total: usize = spans.values().sum();
+                    let cap = rng.gen_range(0, total);
+                    let mut sum = 0;
+                    spans.iter().find(|&(_, &count)| {
+                        sum += count;
+                        sum > cap
+                    }).map(|(span, _)| &span[..]).unwrap()
+                });
+                ret.push_str(span.unwrap_or(" "));
+            }
+            ret.push_str(token);
+        }
+        ret
+    }
+}
+
+/// A strategy for joining a sequence of generated tokens into a string.
+pub trait Detokenizer {
+    /// Joins the tokens into a single string.
+    fn detokenize(&self, tokens: &[&str]) -> String;
+}
+
+impl<F> Detokenizer for F where F: Fn(&[&str]) -> String {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        self(tokens)
+    }
+}
+
+/// Joins tokens with a single space. This is the detokenizer used by `generate_str`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct WhitespaceDetokenizer;
+
+impl Detokenizer for WhitespaceDetokenizer {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        tokens.join(" ")
+    }
+}
+
+/// Joins tokens with a custom separator, e.g. `"/"` for path segments or `""` for characters.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct SeparatorDetokenizer<S>(pub S);
+
+impl<S> Detokenizer for SeparatorDetokenizer<S> where S: AsRef<str> {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        tokens.join(self.0.as_ref())
+    }
+}
+
+/// Joins tokens with single spaces, except that no space is placed before closing punctuation
+/// such as `.,!?;:` and closing brackets, or after opening brackets. Straight double quotes are
+/// treated as alternately opening and closing. This undoes the splitting performed by
+/// `PunctuationTokenizer`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PunctuationDetokenizer;
+
+impl PunctuationDetokenizer {
+    /// Determines whether the token consists entirely of punctuation that attaches to the
+    /// preceding token.
+    fn is_closing(token: &str) -> bool {
+        !token.is_empty() && token.chars().all(|c| ".,!?;:%)]}”’»…".contains(c))
+    }
+
+    /// Determines whether the token consists entirely of punctuation that attaches to the
+    /// following token.
+    fn is_opening(token: &str) -> bool {
+        !token.is_empty() && token.chars().all(|c| "([{“‘«".contains(c))
+    }
+}
+
+impl Detokenizer for PunctuationDetokenizer {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        let mut ret = String::new();
+        let mut in_quote = false;
+        let mut attach_next = true;
+        for &token in tokens {
+            let quote = token == "\"";
+            let attach = attach_next || (quote && in_quote) ||
+                         PunctuationDetokenizer::is_closing(token);
+            if !attach {
+                ret.push(' ');
+            }
+            ret.push_str(token);
+            attach_next = PunctuationDetokenizer::is_opening(token) || (quote && !in_quote);
+            if quote {
+                in_quote = !in_quote;
+            }
+        }
+        ret
+    }
+}
+
+/// Wraps another detokenizer and capitalizes the first letter of every token that starts a
+/// sentence: the first token, and any token following one that ends in `.`, `!` or `?`. Together
+/// with `LowercaseTokenizer`, this lets a chain count differently capitalized forms of a word as
+/// one while still generating properly capitalized text.
+///
+/// ```
+/// use markov::Chain;
+/// use markov::tokenizer::{CapitalizingDetokenizer, LowercaseTokenizer};
+/// use markov::tokenizer::{WhitespaceDetokenizer, WhitespaceTokenizer};
+///
+/// let mut chain = Chain::new();
+/// chain.feed_str_with("I saw it. We LEFT.", &LowercaseTokenizer(WhitespaceTokenizer));
+/// let detokenizer = CapitalizingDetokenizer(WhitespaceDetokenizer);
+/// assert_eq!(chain.generate_str_with(&detokenizer), "I saw it. We left.");
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CapitalizingDetokenizer<D>(pub D);
+
+impl<D> Detokenizer for CapitalizingDetokenizer<D> where D: Detokenizer {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        let mut starts = true;
+        let capitalized: Vec<_> = tokens.iter().map(|&token| {
+            let mut chars = token.chars();
+            let capitalized = match chars.next() {
+                Some(first) if starts => first.to_uppercase().chain(chars).collect(),
+                _ => token.to_owned(),
+            };
+            starts = token.ends_with(['.', '!', '?']);
+            capitalized
+        }).collect();
+        let capitalized: Vec<_> = capitalized.iter().map(|token| &token[..]).collect();
+        self.0.detokenize(&capitalized)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CapitalizingDetokenizer, Detokenizer, LowercaseTokenizer, PunctuationDetokenizer};
+    use super::{PunctuationTokenizer, SeparatorDetokenizer, SpanTokenizer, Tokenizer};
+    use super::{WhitespaceDetokenizer, WhitespaceTokenizer};
+
+    #[test]
+    fn whitespace() {
+        assert_eq!(WhitespaceTokenizer.tokenize(" I\tlike\n cats "), vec!["I", "like", "cats"]);
+    }
+
+    #[test]
+    fn punctuation() {
+        assert_eq!(PunctuationTokenizer.tokenize("\"Hello, world!\" (don't) well-known -x"),
+                   vec!["\"", "Hello", ",", "world", "!", "\"", "(", "don't", ")", "well-known", "-",
+                        "x"]);
+    }
+
+    #[test]
+    fn lowercase() {
+        assert_eq!(LowercaseTokenizer(WhitespaceTokenizer).tokenize("The CAT"), vec!["the", "cat"]);
+    }
+
+    #[test]
+    fn whitespace_detokenizer() {
+        assert_eq!(WhitespaceDetokenizer.detokenize(&["I", "like", "cats"]), "I like cats");
+        assert_eq!(WhitespaceDetokenizer.detokenize(&[]), "");
+    }
+
+    #[test]
+    fn separator_detokenizer() {
+        assert_eq!(SeparatorDetokenizer("/").detokenize(&["usr", "bin"]), "usr/bin");
+        assert_eq!(SeparatorDetokenizer(String::new()).detokenize(&["a", "b"]), "ab");
+    }
+
+    #[test]
+    fn capitalizing_detokenizer() {
+        let detokenizer = CapitalizingDetokenizer(PunctuationDetokenizer);
+        assert_eq!(detokenizer.detokenize(&["hi", "!", "éa", "b", ".", "c"]), "Hi! Éa b. C");
+        assert_eq!(detokenizer.detokenize(&[]), "");
+    }
+
+    #[test]
+    fn punctuation_detokenizer() {
+        let string = "\"Hello, world!\" she said (quietly)... \"Bye.\"";
+        let tokens = PunctuationTokenizer.tokenize(string);
+        let tokens: Vec<_> = tokens.iter().map(|s| &s[..]).collect();
+        assert_eq!(PunctuationDetokenizer.detokenize(&tokens), string);
+    }
+
+    #[test]
+    fn spans() {
+        let tokenizer = SpanTokenizer::new();
+        let tokens = tokenizer.tokenize(" Hello.  World \n");
+        assert_eq!(tokens, vec!["Hello.", "World"]);
+        assert_eq!(tokenizer.detokenize(&["Hello.", "World", "again"]), "Hello.  World again");
+    }
+
+    #[test]
+    fn closure() {
+        let tokenizer = |s: &str| s.split('/').map(|s| s.to_owned()).collect::<Vec<_>>();
+        assert_eq!(tokenizer.tokenize("usr/bin"), vec!["usr", "bin"]);
+    }
+}